@@ -4,75 +4,73 @@ import bpy
 
 BYTES_PER_MES = 4
 SCALE = 1e-2
-STEP_ANGLE_SIZE = 1.8
+HEADER_FORMAT = ">4sHHB3sHHHHBxIIHHQQII"
+HEADER_SIZE = struct.calcsize(HEADER_FORMAT)
+FLAG_ABORTED = 0x0002
+TRAILER_SIZE = 8
+# Distance of the points the device never measured
+MISSING_DISTANCE = 0xFFFFFFFF
 
 data = 0
 
-with open("/home/pitau/data/code/agh/rscanner/client/blender/testfiler.dat", mode="rb") as f:
-    data = list(f.read())
-    
-metadata = data[0:6]
-mes = data[6:]
+with open("/home/pitau/data/code/agh/rscanner/client/blender/testfiler.rscan", mode="rb") as f:
+    data = f.read()
+
+(magic, version, flags, protocol_version, fw_version,
+ point_count, line_count, step_size, line_size, microsteps,
+ point_step_angle, line_step_angle, line_start, point_start,
+ started_at, finished_at, stored_count, crc) = struct.unpack(HEADER_FORMAT, data[0:HEADER_SIZE])
+if magic != b"RSCN" or version != 1:
+    raise ValueError("Not a version 1 .rscan file")
+
+mes = data[HEADER_SIZE:]
+# Aborted scans end with a trailer telling where the scan stopped, it holds no distance
+if flags & FLAG_ABORTED:
+    if mes[-TRAILER_SIZE:-TRAILER_SIZE+4] != b"ABRT":
+        raise ValueError("Aborted scan trailer damaged")
+    mes = mes[:-TRAILER_SIZE]
 mes = [mes[i:i+BYTES_PER_MES] for i in range(0,len(mes), BYTES_PER_MES)]
 
-point_count = metadata[3]
-line_count = metadata[2]
+# Step angles are stored in microdegrees per full step, step sizes are counted in microsteps
+POINT_ANGLE_SIZE = point_step_angle * 1e-6 * step_size / max(microsteps, 1)
+LINE_ANGLE_SIZE = line_step_angle * 1e-6 * line_size / max(microsteps, 1)
+
+def position(index):
+    """Line and point of a measurement, the serpentine starts at the stored start position like ScanHeader::position"""
+    points = max(point_count, 1)
+    start = min(point_start, points - 1)
+    start_offset = start if line_start % 2 == 0 else points - 1 - start
+    line, offset = divmod(line_start * points + start_offset + index, points)
+    point = offset if line % 2 == 0 else points - 1 - offset
+    return line, point
+
 vertices = []
+# Vertex index of every measured grid position
+grid = {}
+
+for index, raw in enumerate(mes):
+    (v,) = struct.unpack(">I", bytes(raw))
+    if v == MISSING_DISTANCE:
+        continue
+    line, point = position(index)
+    vertex = (
+        math.sin(math.radians(point * POINT_ANGLE_SIZE)) * v * SCALE * math.cos(math.radians(line * LINE_ANGLE_SIZE)),
+        math.cos(math.radians(point * POINT_ANGLE_SIZE)) * v * SCALE * math.cos(math.radians(line * LINE_ANGLE_SIZE)),
+        math.sin(math.radians(line * LINE_ANGLE_SIZE)) * v * SCALE
+    )
+    grid[(line, point)] = len(vertices)
+    vertices.append(vertex)
 
-cnt = 0
-point = 0
-line = 0
-side = False
-print(len(mes))
-for i in mes:
-    if not side:
-        cnt += 1
-        (v,) = struct.unpack(">I", bytes(i))
-        vertex = (
-            math.sin(math.radians(point * STEP_ANGLE_SIZE)) * v * SCALE * math.cos(math.radians(line * STEP_ANGLE_SIZE)),
-            math.cos(math.radians(point * STEP_ANGLE_SIZE)) * v * SCALE * math.cos(math.radians(line * STEP_ANGLE_SIZE)),
-            math.sin(math.radians(line * STEP_ANGLE_SIZE)) * v * SCALE
-        )
-        vertices.append(vertex)
-        point += 1
-        if point == point_count:
-            point -= 1
-            side = not side
-            line += 1
-            continue
-    
-    if side:
-        cnt += 1
-        (v,) = struct.unpack(">I", bytes(i))
-        vertex = (
-            math.sin(math.radians(point * STEP_ANGLE_SIZE)) * v * SCALE * math.cos(math.radians(line * STEP_ANGLE_SIZE)),
-            math.cos(math.radians(point * STEP_ANGLE_SIZE)) * v * SCALE * math.cos(math.radians(line * STEP_ANGLE_SIZE)),
-            math.sin(math.radians(line * STEP_ANGLE_SIZE)) * v * SCALE
-        )
-        vertices.append(vertex)
-        point -= 1
-        if point == -1:
-            point += 1
-            side = not side
-            line += 1
-            continue
-            
-print(cnt)
+print(len(vertices))
 
+# Quads between two neighbouring lines, only where all four corners have been measured
 faces = []
-side = False
-for line in range(0
-, line_count-1):
+for line in range(0, line_count-1):
     for point in range(0, point_count-1):
-        if not side:
-            face = [
-                line * point_count + point,
-                line * point_count + point + 1,
-                (line + 1) * point_count + point_count - point - 2,
-                (line + 1) * point_count + point_count - point - 1,
-            ]
-            faces.append(face)
-    
+        corners = [(line, point), (line, point + 1), (line + 1, point + 1), (line + 1, point)]
+        if all(corner in grid for corner in corners):
+            faces.append([grid[corner] for corner in corners])
+
 scan_mesh = bpy.data.meshes.new(name="ScannedMesh")
 scan_mesh.from_pydata(vertices, [], faces)
 #scan_mesh.from_pydata(vertices, [], []) # for debug
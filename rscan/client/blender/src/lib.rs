@@ -0,0 +1,167 @@
+//! Synthetic scan data for the Blender importer and the calibration tests.
+
+use rand::distributions::Distribution;
+
+use scan_export::calibrate::Plane;
+use scan_export::geometry::ScannerGeometry;
+use scan_format::ScanHeader;
+
+/// Random distances around 1000 in the grid order
+///
+/// steps - points of a line
+/// lines - number of lines
+pub fn generate_points(
+    steps: u16,
+    lines: u16) -> Vec<Vec<u32>> {
+
+    let mut mesh = Vec::<Vec<u32>>::new();
+
+    let mut rng = rand::thread_rng();
+    let normal = rand_distr::Normal::<f32>::new(1000.0, 25.0).unwrap();
+
+    for _line_number in 0..lines {
+        let mut line = Vec::<u32>::new();
+        for _point in 0..steps {
+            line.push(normal.sample(&mut rng) as u32);
+        }
+        mesh.push(line);
+    }
+    mesh
+}
+
+/// Raw distances a scanner with the given geometry measures on a flat wall, in the measurement order
+///
+/// header - scan parameters, the step angles of the geometry are taken from it when not set
+/// geometry - true geometry of the simulated scanner
+/// wall - wall in front of the scanner
+/// noise - standard deviation of the raw distance
+pub fn plane_distances(header: &ScanHeader, geometry: &ScannerGeometry, wall: &Plane, noise: f64) -> Vec<u32> {
+    let geometry = geometry.with_header(header);
+    let mut rng = rand::thread_rng();
+    let noise = rand_distr::Normal::<f64>::new(0.0, noise).unwrap();
+
+    (0..header.expected_points())
+        .map(|index| {
+            let (line, point) = header.position(index);
+            let (origin, direction) = geometry.beam(line, point);
+            let dot = |a: &[f64; 3], b: &[f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+            // Beams parallel to the wall or pointing away from it get no return
+            let along = (wall.offset - dot(&wall.normal, &origin)) / dot(&wall.normal, &direction);
+            if !along.is_finite() || along <= 0.0 { return 0; }
+
+            let raw = (along - geometry.distance_offset) / geometry.distance_scale + noise.sample(&mut rng);
+            raw.round().max(0.0) as u32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use scan_export::calibrate::{calibrate, CalibrationScan, Parameter, Plane};
+    use scan_export::geometry::ScannerGeometry;
+    use scan_format::{ScanHeader, ScanReader, ScanWriter};
+
+    use super::plane_distances;
+
+    /// Scanner with a misaligned line axis and a biased lidar
+    const TRUE_GEOMETRY: ScannerGeometry = ScannerGeometry {
+        point_step_angle: None,
+        line_step_angle: None,
+        point_zero: -30.0,
+        line_zero: 2.5,
+        distance_scale: 1.02e-2,
+        distance_offset: 0.4,
+        line_axis_offset: [0.0, 0.0, 0.0],
+        emitter_offset: [0.0, 0.0, 0.0],
+        line_axis_tilt: 0.0,
+        point_axis_tilt: [0.0, 0.0],
+    };
+
+    fn wall_scan(wall: &Plane, target: Option<Plane>) -> CalibrationScan {
+        stepped_wall_scan(wall, target, 1)
+    }
+
+    /// Scan of the wall with the given motor steps between two lines
+    fn stepped_wall_scan(wall: &Plane, target: Option<Plane>, line_step_size: u16) -> CalibrationScan {
+        let mut header = ScanHeader::new(34, 20);
+        header.line_step_size = line_step_size;
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), header.clone()).unwrap();
+        for distance in plane_distances(&header, &TRUE_GEOMETRY, wall, 0.5) {
+            writer.push(distance).unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+        CalibrationScan::read(ScanReader::new(Cursor::new(file)).unwrap(), target).unwrap()
+    }
+
+    #[test]
+    fn plane_distances_follow_geometry() {
+        let header = ScanHeader::new(4, 3);
+        let geometry = ScannerGeometry { distance_offset: 1.0, ..Default::default() };
+        let wall = Plane::new([0.0, 1.0, 0.0], 10.0).unwrap();
+
+        let distances = plane_distances(&header, &geometry, &wall, 0.0);
+        assert_eq!(distances.len(), 12);
+        // Straight beam hits the wall after 9 raw units of 1e-2 past the offset
+        assert_eq!(distances[0], 900);
+        // Serpentine order, the second line starts at its last point
+        assert_eq!(distances[3], 904);
+        assert_eq!(distances[4], 905);
+        assert_eq!(distances[7], 900);
+    }
+
+    #[test]
+    fn calibration_flattens_walls() {
+        let front = Plane::new([0.0, 1.0, 0.0], 8.0).unwrap();
+        let corner = Plane::new([1.0, 1.0, 0.3], 9.0).unwrap();
+        let scans = [wall_scan(&front, None), wall_scan(&corner, None)];
+
+        let initial = ScannerGeometry { point_zero: -30.0, distance_scale: 1.02e-2, ..Default::default() };
+        let result = calibrate(&scans, &initial, &[Parameter::DistanceOffset, Parameter::LineZero]).unwrap();
+
+        // Only the distance noise is left
+        assert!(result.rms_after < result.rms_before / 2.0 && result.rms_after < 0.01, "{:?}", result);
+        assert!((result.geometry.distance_offset - 0.4).abs() < 0.05, "{:?}", result.geometry);
+        assert!((result.geometry.line_zero - 2.5).abs() < 0.2, "{:?}", result.geometry);
+    }
+
+    #[test]
+    fn calibration_keeps_step_angles_of_every_scan() {
+        let front = Plane::new([0.0, 1.0, 0.0], 8.0).unwrap();
+        let corner = Plane::new([1.0, 1.0, 0.3], 9.0).unwrap();
+        let scans = [stepped_wall_scan(&front, None, 1), stepped_wall_scan(&corner, None, 2)];
+        assert!((scans[1].header.line_angle() - 2.0 * scans[0].header.line_angle()).abs() < 1e-9);
+
+        let initial = ScannerGeometry { point_zero: -30.0, distance_scale: 1.02e-2, ..Default::default() };
+        let result = calibrate(&scans, &initial, &[Parameter::DistanceOffset, Parameter::LineZero]).unwrap();
+
+        // Second scan projected with the angles of the first one would not be flat
+        assert!(result.rms_after < 0.01, "{:?}", result);
+        assert!((result.geometry.line_zero - 2.5).abs() < 0.2, "{:?}", result.geometry);
+        assert_eq!(result.geometry.line_step_angle, None);
+    }
+
+    #[test]
+    fn plane_needs_normal() {
+        assert_eq!(Plane::new([0.0, 0.0, 0.0], 5.0), None);
+        assert_eq!(Plane::new([0.0, 0.0, 2.0], 5.0), Some(Plane { normal: [0.0, 0.0, 1.0], offset: 2.5 }));
+    }
+
+    #[test]
+    fn calibration_with_known_plane() {
+        let wall = Plane::new([0.2, 1.0, 0.1], 7.0).unwrap();
+        let scans = [wall_scan(&wall, Some(wall))];
+
+        let parameters = [Parameter::DistanceOffset, Parameter::LineZero, Parameter::DistanceScale, Parameter::PointZero];
+        let result = calibrate(&scans, &ScannerGeometry::default(), &parameters).unwrap();
+
+        let geometry = result.geometry;
+        assert!(result.rms_after < 0.02, "{:?}", result);
+        assert!((geometry.distance_scale / 1.02e-2 - 1.0).abs() < 0.01, "{:?}", geometry);
+        assert!((geometry.distance_offset - 0.4).abs() < 0.05, "{:?}", geometry);
+        assert!((geometry.point_zero + 30.0).abs() < 0.2, "{:?}", geometry);
+        assert!((geometry.line_zero - 2.5).abs() < 0.2, "{:?}", geometry);
+    }
+}
@@ -2,12 +2,11 @@
 
 use std::sync::{Arc, Mutex};
 
-use scanner_comms::{self, packets::Packet};
+use scanner_comms::{self, packets::{AnyPacket, Packet}};
 
 use tokio::{io::{AsyncReadExt, AsyncWriteExt, WriteHalf}, sync::mpsc::Sender};
 use tokio_serial::{SerialPortBuilderExt, SerialStream};
 
-mod coder;
 
 enum State {
         Idle,
@@ -57,7 +56,7 @@ async fn main() {
                     buf.push(val);
                     if val == 0 {
                         // Place decode code here!
-                        let ret = coder::decode_packet(&mut buf);
+                        let ret = AnyPacket::decode(&buf);
                         match ret {
                             Err(_) => {
                                 buf = Vec::<u8>::new();
@@ -65,10 +64,10 @@ async fn main() {
                             }
                             Ok(obj) => {
                                 match obj {
-                                    coder::PackType::Ok(pack) => println!("Decoded {:?}", pack.header.crc),
-                                    coder::PackType::Err(pack) => println!("Decoded error {:?} in packet {:?}", pack.error as u8, pack.packet_id),
-                                    coder::PackType::Prog(pack) => { tokio::spawn(handle_prog(send_chan.clone(), pack)); },
-                                    coder::PackType::Fin(pack) => println!("Decoded fin"),
+                                    AnyPacket::Ok(pack) => println!("Decoded {:?}", pack.header.crc),
+                                    AnyPacket::Err(pack) => println!("Decoded error {:?} in packet {:?}", pack.error as u8, pack.packet_id),
+                                    AnyPacket::Prog(pack) => { tokio::spawn(handle_prog(send_chan.clone(), pack)); },
+                                    AnyPacket::Fin(pack) => println!("Decoded fin"),
                                     _ => println!("Other pack!"),
                                 };
                             }
@@ -92,11 +91,8 @@ async fn main() {
 
 async fn handle_prog(mut tx_chan: Sender<Vec<u8>>, pack: scanner_comms::packets::packet_prog::ProgPacket) {
     println!("Got Prog!");
-    let ok_ret = scanner_comms::packets::packet_ok::OkPacket::new(pack.header.packet_id);
-    let mut buf: Vec<u8> = Vec::with_capacity(scanner_comms::packets::packet_ok::OkPacket::size_of() + 2);
-    
-    let size = ok_ret.serialize(buf.as_mut_ptr(), 9);
-    unsafe { buf.set_len(size); }
+    let ok_ret = scanner_comms::packets::packet_ok::OkPacket::new(pack.header.packet_id.wrapping_add(1), pack.header.packet_id);
+    let buf = ok_ret.encode().to_vec();
     tx_chan.send(buf).await.unwrap();
     
 }
@@ -0,0 +1,79 @@
+use std::process::ExitCode;
+
+use scan_format::FormatError;
+use scanner_session::SessionError;
+
+/// Failure of a CLI command, every kind maps to its own exit code.
+///
+/// Io - the serial port failed, exit code 1
+/// NoResponse - the device did not acknowledge or went silent, exit code 3
+/// Rejected - the device answered the command with ERR, exit code 4
+/// UnsupportedVersion - the device speaks other protocol version, exit code 5
+/// Incomplete - the scan ended with points missing, exit code 6
+/// File - the scan file could not be written, exit code 7
+/// Interrupted - the scan has been aborted by the user, exit code 130
+#[derive(Debug)]
+pub enum CliError {
+    Io(std::io::Error),
+    NoResponse(&'static str),
+    Rejected(String),
+    UnsupportedVersion(u8),
+    Incomplete { expected: u32, got: u32 },
+    File(FormatError),
+    Interrupted,
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            CliError::Io(_) => 1,
+            CliError::NoResponse(_) => 3,
+            CliError::Rejected(_) => 4,
+            CliError::UnsupportedVersion(_) => 5,
+            CliError::Incomplete { .. } => 6,
+            CliError::File(_) => 7,
+            CliError::Interrupted => 130,
+        })
+    }
+}
+
+impl std::fmt::Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Io(e) => write!(f, "serial port error: {}", e),
+            CliError::NoResponse(what) => write!(f, "device does not respond, {}", what),
+            CliError::Rejected(why) => write!(f, "device rejected the command: {}", why),
+            CliError::UnsupportedVersion(version) => write!(f, "device speaks protocol version {}, client requires {}", version, scanner_comms::packets::PROTOCOL_VERSION),
+            CliError::Incomplete { expected, got } => write!(f, "scan incomplete, got {} of {} points", got, expected),
+            CliError::File(e) => write!(f, "scan file error: {}", e),
+            CliError::Interrupted => write!(f, "interrupted, scan aborted"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<std::io::Error> for CliError {
+    fn from(e: std::io::Error) -> Self {
+        CliError::Io(e)
+    }
+}
+
+impl From<FormatError> for CliError {
+    fn from(e: FormatError) -> Self {
+        CliError::File(e)
+    }
+}
+
+impl From<SessionError> for CliError {
+    fn from(e: SessionError) -> Self {
+        match e {
+            SessionError::Closed => CliError::NoResponse("serial port closed"),
+            SessionError::NoResponse => CliError::NoResponse("timed out"),
+            SessionError::Rejected(why) => CliError::Rejected(why.to_string()),
+            SessionError::UnsupportedVersion(version) => CliError::UnsupportedVersion(version),
+            SessionError::Io(kind) => CliError::Io(kind.into()),
+            e @ (SessionError::Unsupported(_) | SessionError::InvalidState(_) | SessionError::Busy | SessionError::Complete) => CliError::Rejected(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+// Copyright (C) 2024 pitau
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use log::warn;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_serial::SerialPortBuilderExt;
+
+use scan_format::{FormatError, ScanHeader, ScanWriter};
+use scanner_comms::packets::Axis;
+use scanner_session::{ScanParams, ScannerSession, SessionConfig, SessionEvent};
+
+mod error;
+
+use error::CliError;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum AxisArg {
+    Horizon,
+    Azimuth,
+}
+
+/// Headless client of the scanner
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Serial port of the scanner
+    #[arg(short, long)]
+    port: String,
+
+    /// Baud rate of the serial port
+    #[arg(short, long, default_value_t = 115_200)]
+    baud: u32,
+
+    /// Seconds the device may stay silent while a response is expected
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Moves a motor by the given number of steps, negative steps turn counter-clockwise
+    Move {
+        #[arg(long, value_enum)]
+        axis: AxisArg,
+        #[arg(long, allow_negative_numbers = true)]
+        steps: i32,
+    },
+    /// Runs a scan and writes it to a .rscan file
+    Scan {
+        /// Points of a line
+        #[arg(long)]
+        points: u16,
+        /// Number of lines
+        #[arg(long)]
+        lines: u16,
+        /// Motor steps between two points
+        #[arg(long, default_value_t = 1)]
+        point_step_size: u16,
+        /// Motor steps between two lines
+        #[arg(long, default_value_t = 1)]
+        line_step_size: u16,
+        /// Microsteps per full step, 0 keeps the device default
+        #[arg(long, default_value_t = 0)]
+        microsteps: u8,
+        /// Target scan file, overwritten if it exists
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Delete the file of an aborted scan instead of keeping the received points
+        #[arg(long)]
+        discard_aborted: bool,
+    },
+    /// Continues an interrupted scan stored in a .rscan file
+    Resume {
+        /// Partial scan file, the scan continues after its last point
+        file: PathBuf,
+        /// Delete the file if the resumed scan gets aborted again
+        #[arg(long)]
+        discard_aborted: bool,
+    },
+    /// Aborts the running scan
+    Abort,
+    /// Prints the description of the device
+    Info,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::init();
+    let args = Args::parse();
+
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            e.exit_code()
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), CliError> {
+    #[allow(unused_mut)]
+    let mut port = tokio_serial::new(&args.port, args.baud).open_native_async().map_err(std::io::Error::from)?;
+    #[cfg(unix)]
+    port.set_exclusive(false).map_err(std::io::Error::from)?;
+
+    let config = SessionConfig { idle_timeout: Duration::from_secs(args.timeout), ..SessionConfig::default() };
+    let (session, mut events) = ScannerSession::spawn(port, config);
+
+    match args.command {
+        Command::Info => {
+            let device = session.connect().await?;
+            println!("protocol version: {}", device.protocol_version);
+            println!("firmware: {}.{}.{}", device.fw_version[0], device.fw_version[1], device.fw_version[2]);
+            println!("horizon step angle: {} deg", device.horizon_step_angle as f64 / 1_000_000.0);
+            println!("azimuth step angle: {} deg", device.azimuth_step_angle as f64 / 1_000_000.0);
+            println!("max points per line: {}", device.max_steps_per_line);
+            println!("capabilities: {:#06x}", device.capabilities);
+        }
+        Command::Move { axis, steps } => {
+            session.connect().await?;
+            let motor = match axis {
+                AxisArg::Horizon => Axis::Horizon,
+                AxisArg::Azimuth => Axis::Azimuth,
+            };
+            session.move_axis(motor, steps).await?;
+            println!("Moved {:?} by {} steps", axis, steps);
+        }
+        Command::Abort => {
+            session.abort().await?;
+            println!("Abort acknowledged");
+        }
+        Command::Scan { points, lines, point_step_size, line_step_size, microsteps, output, discard_aborted } => {
+            let device = session.connect().await?;
+            let params = ScanParams { number_of_points: points, number_of_lines: lines, point_step_size, line_step_size, microsteps };
+
+            // File is created upfront so a bad path fails before the motors move
+            let mut header = ScanHeader::new(points, lines);
+            header.protocol_version = device.protocol_version;
+            header.fw_version = device.fw_version;
+            let writer = ScanWriter::create(&output, header)?;
+
+            session.start_scan(params).await?;
+            receive(&session, &mut events, writer, &output, discard_aborted).await?;
+        }
+        Command::Resume { file, discard_aborted } => {
+            session.connect().await?;
+            let writer = ScanWriter::resume(&file)?;
+            let header = writer.header().clone();
+            eprintln!("Resuming at point {} of {}", header.point_count, header.expected_points());
+
+            session.resume_scan(header.clone(), header.point_count).await?;
+            receive(&session, &mut events, writer, &file, discard_aborted).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Stores the measurements of the running scan until it ends
+///
+/// writer - the scan file, points already in it count as received
+/// output - path of the scan file
+/// discard_aborted - delete the file if the scan gets aborted
+async fn receive(session: &ScannerSession, events: &mut UnboundedReceiver<SessionEvent>, mut writer: ScanWriter<std::fs::File>, output: &Path, discard_aborted: bool) -> Result<(), CliError> {
+    let expected = writer.header().expected_points();
+    let mut received = writer.header().point_count;
+    let mut missing = 0;
+    let reported = loop {
+        let event = tokio::select! {
+            event = events.recv() => event.ok_or(CliError::NoResponse("serial port closed"))?,
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!();
+                match session.abort().await {
+                    Ok(()) => close_aborted(writer, output, discard_aborted)?,
+                    Err(e) => {
+                        warn!("Abort not acknowledged: {}", e);
+                        writer.sync_header()?;
+                    }
+                }
+                return Err(CliError::Interrupted);
+            }
+        };
+        match event {
+            SessionEvent::ScanStarted(header) => {
+                writer.replace_header(header)?;
+            }
+            SessionEvent::Measurement { distance, status, .. } => {
+                match status.has_distance() {
+                    true => writer.push(distance)?,
+                    false => {
+                        writer.push_missing()?;
+                        missing += 1;
+                    }
+                }
+                received += 1;
+                eprint!("\r{}/{} points", received, expected);
+                let _ = std::io::stderr().flush();
+            }
+            SessionEvent::Missing { index, line, point } => {
+                warn!("Point {} at line {} point {} missing", index, line, point);
+                writer.push_missing()?;
+                missing += 1;
+                received += 1;
+            }
+            SessionEvent::ScanFinished { reported, .. } => break reported,
+            SessionEvent::ScanAborted { .. } => {
+                eprintln!();
+                close_aborted(writer, output, discard_aborted)?;
+                return Err(CliError::Incomplete { expected, got: received - missing });
+            }
+            SessionEvent::Fault(e) => {
+                eprintln!();
+                writer.sync_header()?;
+                return Err(e.into());
+            }
+            _ => (),
+        }
+    };
+    eprintln!();
+
+    writer.finish()?;
+    if received != expected || reported != received || missing > 0 {
+        return Err(CliError::Incomplete { expected, got: received - missing });
+    }
+    println!("Scan of {} points written to {:?}", received, output);
+    Ok(())
+}
+
+/// Closes the file of the aborted scan with the trailer or deletes it
+///
+/// writer - the scan file
+/// output - path of the scan file
+/// discard - delete the file instead of keeping it
+fn close_aborted(writer: ScanWriter<std::fs::File>, output: &Path, discard: bool) -> Result<(), CliError> {
+    let received = writer.header().point_count;
+    writer.abort()?;
+    if discard {
+        // Failure to delete is a scan file error, not a port one
+        std::fs::remove_file(output).map_err(FormatError::Io)?;
+        eprintln!("Aborted scan discarded");
+    } else {
+        eprintln!("Aborted at point {}, partial scan kept in {:?}", received, output);
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+
+use scan_export::calibrate::{calibrate, CalibrationScan, Parameter, Plane};
+use scan_export::geometry::ScannerGeometry;
+use scan_format::ScanReader;
+
+/// Fits the scanner calibration to scans of a flat wall
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Scans of a flat target, each may see a different wall
+    #[arg(required = true)]
+    scans: Vec<PathBuf>,
+
+    /// Target calibration file
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Calibration to start from, mechanical offsets are kept from it
+    #[arg(short, long)]
+    initial: Option<PathBuf>,
+
+    /// Known plane of the target as `nx,ny,nz,offset` in output units, fits the distance scale and point zero too
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    plane: Option<Vec<f64>>,
+
+    /// Fit the step angles of both axes as well
+    #[arg(long)]
+    fit_steps: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let initial = match &args.initial {
+        Some(path) => ScannerGeometry::load(path).with_context(|| format!("Cannot load calibration {:?}", path))?,
+        None => ScannerGeometry::default(),
+    };
+    let target = match args.plane.as_deref() {
+        Some(&[nx, ny, nz, offset]) => match Plane::new([nx, ny, nz], offset) {
+            Some(plane) => Some(plane),
+            None => bail!("The plane normal cannot be zero"),
+        },
+        Some(_) => bail!("The plane needs 4 values"),
+        None => None,
+    };
+
+    let mut scans = Vec::new();
+    for path in &args.scans {
+        let reader = ScanReader::open(path).with_context(|| format!("Cannot open {:?}", path))?;
+        scans.push(CalibrationScan::read(reader, target).with_context(|| format!("Cannot read {:?}", path))?);
+    }
+
+    let mut parameters = vec![Parameter::DistanceOffset, Parameter::LineZero];
+    if target.is_some() {
+        parameters.extend([Parameter::DistanceScale, Parameter::PointZero]);
+    }
+    if args.fit_steps {
+        parameters.extend([Parameter::PointStepAngle, Parameter::LineStepAngle]);
+    }
+
+    let result = calibrate(&scans, &initial, &parameters)?;
+    result.geometry.save(&args.output).with_context(|| format!("Cannot write {:?}", args.output))?;
+
+    println!("Fitted {:?} in {} iterations", parameters, result.iterations);
+    println!("RMS distance from the plane {:.4} -> {:.4}", result.rms_before, result.rms_after);
+    println!("Calibration written to {:?}", args.output);
+    Ok(())
+}
@@ -0,0 +1,313 @@
+use std::io::Read;
+
+use scan_format::{FormatError, ScanHeader, ScanPoint, ScanReader};
+
+use super::geometry::{CalibrationError, ScannerGeometry};
+
+const MAX_ITERATIONS: usize = 100;
+
+/// Calibration parameter fitted by `calibrate`.
+///
+/// DistanceScale, DistanceOffset - raw distance conversion, the scale needs a known target plane
+/// PointZero - point axis angle at step zero, needs a known target plane
+/// LineZero - line axis angle at step zero
+/// PointStepAngle, LineStepAngle - degrees per step of each axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parameter {
+    DistanceScale,
+    DistanceOffset,
+    PointZero,
+    LineZero,
+    PointStepAngle,
+    LineStepAngle,
+}
+
+impl Parameter {
+    fn get(self, geometry: &ScannerGeometry) -> f64 {
+        match self {
+            Parameter::DistanceScale => geometry.distance_scale,
+            Parameter::DistanceOffset => geometry.distance_offset,
+            Parameter::PointZero => geometry.point_zero,
+            Parameter::LineZero => geometry.line_zero,
+            Parameter::PointStepAngle => geometry.point_step_angle.unwrap_or_default(),
+            Parameter::LineStepAngle => geometry.line_step_angle.unwrap_or_default(),
+        }
+    }
+
+    fn set(self, geometry: &mut ScannerGeometry, value: f64) {
+        match self {
+            Parameter::DistanceScale => geometry.distance_scale = value,
+            Parameter::DistanceOffset => geometry.distance_offset = value,
+            Parameter::PointZero => geometry.point_zero = value,
+            Parameter::LineZero => geometry.line_zero = value,
+            Parameter::PointStepAngle => geometry.point_step_angle = Some(value),
+            Parameter::LineStepAngle => geometry.line_step_angle = Some(value),
+        }
+    }
+}
+
+/// Plane of points `x` satisfying `normal . x = offset`.
+///
+/// normal - unit normal
+/// offset - distance of the plane from the origin along the normal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: [f64; 3],
+    pub offset: f64,
+}
+
+impl Plane {
+    /// Plane with the normal scaled to unit length
+    ///
+    /// @ret Option<Plane> - None if the normal has no direction
+    pub fn new(normal: [f64; 3], offset: f64) -> Option<Self> {
+        let len = dot(&normal, &normal).sqrt();
+        if !len.is_normal() { return None; }
+        Some(Plane { normal: normal.map(|v| v / len), offset: offset / len })
+    }
+
+    /// Signed distance of the point from the plane
+    pub fn distance(&self, point: &[f64; 3]) -> f64 {
+        dot(&self.normal, point) - self.offset
+    }
+
+    /// Least squares plane of the points
+    ///
+    /// @ret Option<Plane> - None for less than three points
+    pub fn fit(points: &[[f64; 3]]) -> Option<Plane> {
+        if points.len() < 3 { return None; }
+
+        let n = points.len() as f64;
+        let centroid = [0, 1, 2].map(|i| points.iter().map(|p| p[i]).sum::<f64>() / n);
+        let mut covariance = [[0.0; 3]; 3];
+        for p in points {
+            let d = [0, 1, 2].map(|i| p[i] - centroid[i]);
+            for (i, row) in covariance.iter_mut().enumerate() {
+                for (j, cell) in row.iter_mut().enumerate() {
+                    *cell += d[i] * d[j];
+                }
+            }
+        }
+
+        // Normal is the direction of the least spread
+        let normal = smallest_eigenvector(covariance);
+        Some(Plane { normal, offset: dot(&normal, &centroid) })
+    }
+}
+
+/// Scan of a flat target used for the calibration.
+///
+/// header - header of the scan
+/// points - measured points, the ones without a return are left out
+/// target - known plane of the target in output coordinates, fitted to the points when not known
+pub struct CalibrationScan {
+    pub header: ScanHeader,
+    pub points: Vec<ScanPoint>,
+    pub target: Option<Plane>,
+}
+
+impl CalibrationScan {
+    /// Reads the whole scan
+    ///
+    /// reader - freshly opened scan
+    /// target - known plane of the target
+    pub fn read<R: Read>(mut reader: ScanReader<R>, target: Option<Plane>) -> Result<Self, FormatError> {
+        let header = reader.header().clone();
+        let points = reader.by_ref()
+            .filter(|point| !matches!(point, Ok(point) if point.distance == 0 || point.is_missing()))
+            .collect::<Result<Vec<ScanPoint>, FormatError>>()?;
+        Ok(CalibrationScan { header, points, target })
+    }
+
+    /// Distances of the projected points from the target plane
+    fn residuals(&self, geometry: &ScannerGeometry, out: &mut Vec<f64>) {
+        let geometry = geometry.with_header(&self.header);
+        let positions: Vec<[f64; 3]> = self.points.iter()
+            .map(|point| {
+                let (origin, direction) = geometry.beam(point.line, point.point);
+                let distance = geometry.distance(point.distance);
+                [0, 1, 2].map(|i| origin[i] + direction[i] * distance)
+            })
+            .collect();
+
+        let plane = match self.target {
+            Some(plane) => plane,
+            None => match Plane::fit(&positions) {
+                Some(plane) => plane,
+                None => return,
+            },
+        };
+        out.extend(positions.iter().map(|p| plane.distance(p)));
+    }
+}
+
+/// Outcome of the calibration.
+///
+/// geometry - calibrated scanner model
+/// rms_before, rms_after - root mean square distance of the points from the planes with the initial and calibrated model
+/// iterations - number of solver iterations
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    pub geometry: ScannerGeometry,
+    pub rms_before: f64,
+    pub rms_after: f64,
+    pub iterations: usize,
+}
+
+/// Fits the parameters so that the scans lie on their planes, Levenberg-Marquardt least squares
+///
+/// scans - scans of flat targets
+/// initial - starting model, missing step angles are taken from every scan, the fitted ones start from the first scan
+/// parameters - parameters to fit, the rest is kept from the initial model
+pub fn calibrate(scans: &[CalibrationScan], initial: &ScannerGeometry, parameters: &[Parameter]) -> Result<Calibration, CalibrationError> {
+    let first = scans.first().ok_or(CalibrationError::NotEnoughPoints)?;
+    // Step angles not fitted keep following the scan headers
+    let mut geometry = *initial;
+    if parameters.contains(&Parameter::PointStepAngle) {
+        geometry.point_step_angle.get_or_insert(first.header.point_angle());
+    }
+    if parameters.contains(&Parameter::LineStepAngle) {
+        geometry.line_step_angle.get_or_insert(first.header.line_angle());
+    }
+
+    let residuals = |geometry: &ScannerGeometry| {
+        let mut out = Vec::new();
+        for scan in scans {
+            scan.residuals(geometry, &mut out);
+        }
+        out
+    };
+    let cost = |r: &[f64]| r.iter().map(|v| v * v).sum::<f64>();
+
+    let mut current = residuals(&geometry);
+    // Unknown planes take three degrees of freedom each
+    let planes = scans.iter().filter(|scan| scan.target.is_none()).count();
+    if current.len() < parameters.len() + 3 * planes + 1 {
+        return Err(CalibrationError::NotEnoughPoints);
+    }
+    let rms_before = (cost(&current) / current.len() as f64).sqrt();
+
+    let count = parameters.len();
+    let mut lambda = 1e-3;
+    let mut iterations = 0;
+    while iterations < MAX_ITERATIONS && count > 0 {
+        iterations += 1;
+
+        // Forward difference Jacobian
+        let mut jacobian = Vec::with_capacity(count);
+        for parameter in parameters {
+            let value = parameter.get(&geometry);
+            let step = 1e-6 * value.abs().max(1e-3);
+            let mut probe = geometry;
+            parameter.set(&mut probe, value + step);
+            let shifted = residuals(&probe);
+            jacobian.push(shifted.iter().zip(&current).map(|(s, c)| (s - c) / step).collect::<Vec<f64>>());
+        }
+
+        let mut normal = vec![vec![0.0; count]; count];
+        let mut gradient = vec![0.0; count];
+        for i in 0..count {
+            gradient[i] = jacobian[i].iter().zip(&current).map(|(j, r)| j * r).sum();
+            for k in 0..count {
+                normal[i][k] = jacobian[i].iter().zip(&jacobian[k]).map(|(a, b)| a * b).sum();
+            }
+        }
+        let scale = (0..count).map(|i| normal[i][i]).fold(0.0, f64::max).max(f64::MIN_POSITIVE);
+
+        // Raise the damping until the step improves the fit
+        let before = cost(&current);
+        let mut improved = None;
+        while lambda < 1e12 {
+            let mut damped = normal.clone();
+            for (i, row) in damped.iter_mut().enumerate() {
+                row[i] += lambda * (row[i] + 1e-9 * scale);
+            }
+            let rhs: Vec<f64> = gradient.iter().map(|g| -g).collect();
+            if let Some(delta) = solve(damped, rhs) {
+                let mut candidate = geometry;
+                for (parameter, d) in parameters.iter().zip(&delta) {
+                    parameter.set(&mut candidate, parameter.get(&geometry) + d);
+                }
+                let r = residuals(&candidate);
+                if cost(&r) < before {
+                    improved = Some((candidate, r));
+                    lambda = (lambda / 10.0).max(1e-12);
+                    break;
+                }
+            }
+            lambda *= 10.0;
+        }
+
+        match improved {
+            Some((candidate, r)) => {
+                let after = cost(&r);
+                geometry = candidate;
+                current = r;
+                if before - after <= 1e-12 * before { break; }
+            }
+            None => break,
+        }
+    }
+
+    let rms_after = (cost(&current) / current.len() as f64).sqrt();
+    Ok(Calibration { geometry, rms_before, rms_after, iterations })
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Gaussian elimination with partial pivoting
+fn solve(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&x, &y| a[x][col].abs().total_cmp(&a[y][col].abs()))?;
+        if a[pivot][col].abs() < 1e-300 { return None; }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col].clone();
+        for row in col + 1..n {
+            let factor = a[row][col] / pivot_row[col];
+            for (cell, p) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *cell -= factor * p;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Eigenvector of the smallest eigenvalue of a symmetric matrix, Jacobi rotations
+fn smallest_eigenvector(mut a: [[f64; 3]; 3]) -> [f64; 3] {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..50 {
+        let (p, q) = [(0, 1), (0, 2), (1, 2)].into_iter()
+            .max_by(|&(a1, b1), &(a2, b2)| a[a1][b1].abs().total_cmp(&a[a2][b2].abs()))
+            .unwrap();
+        if a[p][q].abs() < 1e-15 * (a[p][p].abs() + a[q][q].abs()).max(f64::MIN_POSITIVE) { break; }
+
+        let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+        let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+        let c = 1.0 / (t * t + 1.0).sqrt();
+        let s = t * c;
+
+        // A' = J^T A J and V' = V J
+        for row in a.iter_mut().chain(v.iter_mut()) {
+            let (rp, rq) = (row[p], row[q]);
+            row[p] = c * rp - s * rq;
+            row[q] = s * rp + c * rq;
+        }
+        let (rp, rq) = (a[p], a[q]);
+        a[p] = [0, 1, 2].map(|k| c * rp[k] - s * rq[k]);
+        a[q] = [0, 1, 2].map(|k| s * rp[k] + c * rq[k]);
+    }
+
+    let smallest = (0..3).min_by(|&x, &y| a[x][x].total_cmp(&a[y][y])).unwrap();
+    [v[0][smallest], v[1][smallest], v[2][smallest]]
+}
@@ -0,0 +1,198 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use scan_format::{ScanHeader, ScanPoint, DEFAULT_STEP_ANGLE};
+
+/// Distance scale used by the Blender importer, raw lidar units to Blender units
+pub const DEFAULT_SCALE: f64 = 1e-2;
+
+/// Error type of the calibration file handling.
+///
+/// Io - the file could not be read or written
+/// Parse - the file is not a valid calibration
+/// Serialize - the calibration could not be converted to TOML
+/// NotEnoughPoints - the calibration scans do not constrain the fitted parameters
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+    NotEnoughPoints,
+}
+
+impl std::fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CalibrationError::Io(e) => write!(f, "io error: {}", e),
+            CalibrationError::Parse(e) => write!(f, "invalid calibration: {}", e),
+            CalibrationError::Serialize(e) => write!(f, "cannot serialize calibration: {}", e),
+            CalibrationError::NotEnoughPoints => write!(f, "not enough points to fit the calibration"),
+        }
+    }
+}
+
+impl std::error::Error for CalibrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CalibrationError::Io(e) => Some(e),
+            CalibrationError::Parse(e) => Some(e),
+            CalibrationError::Serialize(e) => Some(e),
+            CalibrationError::NotEnoughPoints => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CalibrationError {
+    fn from(e: std::io::Error) -> Self {
+        CalibrationError::Io(e)
+    }
+}
+
+/// Kinematic model of the two-axis scanner.
+///
+/// The point axis is vertical and turns the whole head, the line axis is carried by it and tilts the lidar.
+/// Coordinates follow `blender/script.py`: Y points forward at zero angles, X to the right and Z up.
+/// The default model is exactly the spherical projection of the script.
+///
+/// point_step_angle, line_step_angle - degrees per step of each axis, taken from the scan header when not set
+/// point_zero, line_zero - angle of the axis at step zero in degrees
+/// distance_scale - multiplier of the raw distance
+/// distance_offset - added to the scaled distance, in output units
+/// line_axis_offset - position of the line axis relative to the point axis, in output units
+/// emitter_offset - position of the lidar emitter relative to the line axis, in output units
+/// line_axis_tilt - deviation of the line axis from being perpendicular to the point axis, rotation around Y in degrees
+/// point_axis_tilt - deviation of the point axis from vertical, rotations around X and Y in degrees
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScannerGeometry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub point_step_angle: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_step_angle: Option<f64>,
+    pub point_zero: f64,
+    pub line_zero: f64,
+    pub distance_scale: f64,
+    pub distance_offset: f64,
+    pub line_axis_offset: [f64; 3],
+    pub emitter_offset: [f64; 3],
+    pub line_axis_tilt: f64,
+    pub point_axis_tilt: [f64; 2],
+}
+
+impl Default for ScannerGeometry {
+    fn default() -> Self {
+        ScannerGeometry {
+            point_step_angle: None,
+            line_step_angle: None,
+            point_zero: 0.0,
+            line_zero: 0.0,
+            distance_scale: DEFAULT_SCALE,
+            distance_offset: 0.0,
+            line_axis_offset: [0.0; 3],
+            emitter_offset: [0.0; 3],
+            line_axis_tilt: 0.0,
+            point_axis_tilt: [0.0; 2],
+        }
+    }
+}
+
+impl ScannerGeometry {
+    /// Reads the calibration file, missing keys keep their defaults
+    ///
+    /// path - TOML calibration file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CalibrationError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses the calibration
+    pub fn from_toml(text: &str) -> Result<Self, CalibrationError> {
+        toml::from_str(text).map_err(CalibrationError::Parse)
+    }
+
+    /// Calibration as TOML text
+    pub fn to_toml(&self) -> Result<String, CalibrationError> {
+        toml::to_string(self).map_err(CalibrationError::Serialize)
+    }
+
+    /// Writes the calibration file
+    ///
+    /// path - target TOML file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CalibrationError> {
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    /// Fills the step angles missing in the calibration with the ones the scan has been taken with
+    ///
+    /// header - header of the projected scan
+    pub fn with_header(mut self, header: &ScanHeader) -> Self {
+        self.point_step_angle.get_or_insert(header.point_angle());
+        self.line_step_angle.get_or_insert(header.line_angle());
+        self
+    }
+
+    /// Axis angles of the grid position in degrees
+    ///
+    /// @ret (f64, f64) - angle of the point axis and of the line axis
+    pub fn angles(&self, line: u16, point: u16) -> (f64, f64) {
+        let default = DEFAULT_STEP_ANGLE as f64 / 1_000_000.0;
+        (
+            point as f64 * self.point_step_angle.unwrap_or(default) + self.point_zero,
+            line as f64 * self.line_step_angle.unwrap_or(default) + self.line_zero,
+        )
+    }
+
+    /// Distance along the beam in output units
+    pub fn distance(&self, raw: u32) -> f64 {
+        raw as f64 * self.distance_scale + self.distance_offset
+    }
+
+    /// Lidar beam of the grid position
+    ///
+    /// @ret ([f64; 3], [f64; 3]) - emitter position and unit direction of the beam
+    pub fn beam(&self, line: u16, point: u16) -> ([f64; 3], [f64; 3]) {
+        let (azimuth, elevation) = self.angles(line, point);
+        // The beam leaves the emitter along Y of the lidar frame
+        (self.transform(self.emitter_offset, azimuth, elevation, true), self.transform([0.0, 1.0, 0.0], azimuth, elevation, false))
+    }
+
+    /// Cartesian coordinates of the measurement
+    pub fn project(&self, point: &ScanPoint) -> [f32; 3] {
+        let (origin, direction) = self.beam(point.line, point.point);
+        let distance = self.distance(point.distance);
+        [0, 1, 2].map(|i| (origin[i] + direction[i] * distance) as f32)
+    }
+
+    /// Lidar frame to output coordinates, translations are skipped for directions
+    fn transform(&self, v: [f64; 3], azimuth: f64, elevation: f64, position: bool) -> [f64; 3] {
+        // Tilted lidar carried by the possibly skewed line axis
+        let mut v = rotate_y(rotate_x(v, elevation), self.line_axis_tilt);
+        if position {
+            v = add(v, self.line_axis_offset);
+        }
+
+        // Point axis turns clockwise when seen from above, the base may be out of level
+        let v = rotate_z(v, -azimuth);
+        rotate_x(rotate_y(v, self.point_axis_tilt[1]), self.point_axis_tilt[0])
+    }
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn rotate_x(v: [f64; 3], degrees: f64) -> [f64; 3] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [v[0], v[1] * cos - v[2] * sin, v[1] * sin + v[2] * cos]
+}
+
+fn rotate_y(v: [f64; 3], degrees: f64) -> [f64; 3] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [v[0] * cos + v[2] * sin, v[1], -v[0] * sin + v[2] * cos]
+}
+
+fn rotate_z(v: [f64; 3], degrees: f64) -> [f64; 3] {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    [v[0] * cos - v[1] * sin, v[0] * sin + v[1] * cos, v[2]]
+}
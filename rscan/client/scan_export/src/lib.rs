@@ -0,0 +1,217 @@
+//! Conversion of `.rscan` scans into geometry formats.
+
+pub mod geometry;
+pub mod calibrate;
+pub mod mesh;
+pub mod ply;
+pub mod obj;
+pub mod stl;
+pub mod pcd;
+pub mod table;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use scan_format::{ScanHeader, ScanReader, ScanWriter};
+
+    use super::mesh::{FaceFilter, Mesh, Vertex};
+    use super::obj::write_obj;
+    use super::stl::write_stl;
+    use super::table::{write_csv, write_xyz};
+    use super::pcd::{write_pcd, PcdFormat};
+    use super::ply::{write_ply, PlyFormat};
+    use super::geometry::ScannerGeometry;
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4)
+    }
+
+    /// Serpentine 3 x 3 scan with the distance encoding its grid position
+    fn sample_mesh(count: usize) -> Mesh {
+        let header = ScanHeader::new(3, 3);
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), header).unwrap();
+        for distance in [100, 101, 102, 112, 111, 110, 120, 121, 122].into_iter().take(count) {
+            writer.push(distance).unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+
+        let reader = ScanReader::new(Cursor::new(file)).unwrap();
+        let geometry = ScannerGeometry { distance_scale: 1.0, ..Default::default() };
+        Mesh::from_scan(reader, &geometry).unwrap()
+    }
+
+    #[test]
+    fn default_geometry_matches_script() {
+        let geometry = ScannerGeometry { point_step_angle: Some(90.0), line_step_angle: Some(90.0), ..Default::default() };
+        let point = |line, point| scan_format::ScanPoint { line, point, distance: 1000 };
+
+        assert!(close(geometry.project(&point(0, 0)), [0.0, 10.0, 0.0]));
+        assert!(close(geometry.project(&point(0, 1)), [10.0, 0.0, 0.0]));
+        assert!(close(geometry.project(&point(1, 0)), [0.0, 0.0, 10.0]));
+    }
+
+    #[test]
+    fn geometry_offsets_and_tilts() {
+        let point = |line, point| scan_format::ScanPoint { line, point, distance: 1000 };
+        let geometry = ScannerGeometry {
+            point_step_angle: Some(90.0),
+            line_step_angle: Some(90.0),
+            distance_scale: 1e-2,
+            distance_offset: -1.0,
+            line_axis_offset: [0.0, 1.0, 2.0],
+            emitter_offset: [0.5, 0.0, 0.0],
+            ..Default::default()
+        };
+        // Forward beam, emitter beside the line axis which sits in front of and above the point axis
+        assert!(close(geometry.project(&point(0, 0)), [0.5, 10.0, 2.0]));
+        // Head turned right, offsets turn with it
+        assert!(close(geometry.project(&point(0, 1)), [10.0, -0.5, 2.0]));
+        // Lidar tilted up, only the offsets of the point axis frame stay put
+        assert!(close(geometry.project(&point(1, 0)), [0.5, 1.0, 11.0]));
+
+        let zero = ScannerGeometry { point_zero: -90.0, line_zero: 45.0, ..Default::default() };
+        let half = std::f32::consts::FRAC_1_SQRT_2 * 10.0;
+        assert!(close(zero.project(&point(0, 0)), [-half, 0.0, half]));
+
+        let tilted = ScannerGeometry { point_axis_tilt: [90.0, 0.0], ..Default::default() };
+        assert!(close(tilted.project(&point(0, 0)), [0.0, 0.0, 10.0]));
+        let skewed = ScannerGeometry { line_axis_tilt: 90.0, line_step_angle: Some(90.0), ..Default::default() };
+        assert!(close(skewed.project(&point(1, 0)), [10.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn geometry_toml_round_trip() {
+        let partial = ScannerGeometry::from_toml("distance_offset = 0.25\nemitter_offset = [0.0, 0.1, 0.0]\n").unwrap();
+        assert_eq!(partial, ScannerGeometry { distance_offset: 0.25, emitter_offset: [0.0, 0.1, 0.0], ..Default::default() });
+
+        let full = ScannerGeometry { point_step_angle: Some(0.9), line_axis_tilt: 0.3, ..partial };
+        assert_eq!(ScannerGeometry::from_toml(&full.to_toml().unwrap()).unwrap(), full);
+        assert!(ScannerGeometry::from_toml("distance_scale = \"far\"").is_err());
+    }
+
+    #[test]
+    fn mesh_stitches_serpentine_lines() {
+        let mesh = sample_mesh(9);
+
+        // Vertices in the grid order
+        let distances: Vec<u32> = mesh.vertices.iter().map(|v| v.distance).collect();
+        assert_eq!(distances, vec![100, 101, 102, 110, 111, 112, 120, 121, 122]);
+
+        assert_eq!(mesh.faces, vec![[0, 1, 4, 3], [1, 2, 5, 4], [3, 4, 7, 6], [4, 5, 8, 7]]);
+    }
+
+    #[test]
+    fn mesh_skips_faces_of_missing_points() {
+        // Last line interrupted after the first point
+        let mesh = sample_mesh(7);
+
+        assert_eq!(mesh.vertices.len(), 7);
+        assert_eq!(mesh.faces, vec![[0, 1, 4, 3], [1, 2, 5, 4]]);
+
+        // Centre point never delivered, its marker keeps the rest in place
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), ScanHeader::new(3, 3)).unwrap();
+        for distance in [100, 101, 102, 112, scan_format::MISSING_DISTANCE, 110, 120, 121, 122] {
+            writer.push(distance).unwrap();
+        }
+        let reader = ScanReader::new(Cursor::new(writer.finish().unwrap().into_inner())).unwrap();
+        let mesh = Mesh::from_scan(reader, &ScannerGeometry::default()).unwrap();
+
+        let distances: Vec<u32> = mesh.vertices.iter().map(|v| v.distance).collect();
+        assert_eq!(distances, vec![100, 101, 102, 110, 112, 120, 121, 122]);
+        assert!(mesh.faces.is_empty());
+    }
+
+    #[test]
+    fn ply_ascii_and_binary() {
+        let mesh = sample_mesh(9);
+
+        let mut ascii = Vec::new();
+        write_ply(&mut ascii, &mesh, PlyFormat::Ascii, true).unwrap();
+        let ascii = String::from_utf8(ascii).unwrap();
+        assert!(ascii.starts_with("ply\nformat ascii 1.0\n"));
+        assert!(ascii.contains("element vertex 9\n"));
+        assert!(ascii.contains("element face 4\n"));
+        assert!(ascii.ends_with("4 4 5 8 7\n"));
+
+        let mut binary = Vec::new();
+        write_ply(&mut binary, &mesh, PlyFormat::BinaryLittleEndian, true).unwrap();
+        let body = binary.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+        // 3 floats, 2 ushorts and an uint per vertex, count byte and 4 uints per face
+        assert_eq!(binary.len() - body, 9 * 20 + 4 * 17);
+    }
+
+    #[test]
+    fn faces_triangulated_for_obj_and_stl() {
+        let mesh = sample_mesh(9);
+
+        let mut obj = Vec::new();
+        write_obj(&mut obj, &mesh, true).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 9);
+        assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 8);
+        assert!(obj.contains("\nf 1 2 5\nf 1 5 4\n"));
+
+        let mut stl = Vec::new();
+        write_stl(&mut stl, &mesh).unwrap();
+        assert_eq!(stl.len(), 84 + 8 * 50);
+        assert_eq!(stl[80..84], 8u32.to_le_bytes());
+    }
+
+    #[test]
+    fn filter_drops_faces_across_discontinuities() {
+        let header = ScanHeader::new(3, 2);
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), header).unwrap();
+        // Far object behind the last point of the first line
+        for distance in [100, 100, 900, 100, 100, 100] {
+            writer.push(distance).unwrap();
+        }
+        let file = writer.finish().unwrap().into_inner();
+        let reader = ScanReader::new(Cursor::new(file)).unwrap();
+        let geometry = ScannerGeometry { distance_scale: 1.0, ..Default::default() };
+        let mesh = Mesh::from_scan(reader, &geometry).unwrap();
+
+        let mut jump = Mesh { header: mesh.header.clone(), vertices: mesh.vertices.clone(), faces: mesh.faces.clone() };
+        assert_eq!(jump.filter_faces(&FaceFilter { max_edge: None, max_distance_jump: Some(50) }), 1);
+        assert_eq!(jump.faces, vec![[0, 1, 4, 3]]);
+
+        let mut edge = mesh;
+        assert_eq!(edge.filter_faces(&FaceFilter { max_edge: Some(100.0), max_distance_jump: None }), 1);
+        assert_eq!(edge.faces, vec![[0, 1, 4, 3]]);
+    }
+
+    #[test]
+    fn pcd_ascii_and_binary() {
+        let mesh = sample_mesh(7);
+
+        let mut ascii = Vec::new();
+        write_pcd(&mut ascii, &mesh, PcdFormat::Ascii).unwrap();
+        let ascii = String::from_utf8(ascii).unwrap();
+        assert!(ascii.contains("\nFIELDS x y z line point distance\n"));
+        assert!(ascii.contains("\nPOINTS 7\nDATA ascii\n"));
+        assert_eq!(ascii.lines().skip_while(|l| !l.starts_with("DATA")).count(), 1 + 7);
+
+        let mut binary = Vec::new();
+        write_pcd(&mut binary, &mesh, PcdFormat::Binary).unwrap();
+        let body = binary.windows(12).position(|w| w == b"DATA binary\n").unwrap() + 12;
+        assert_eq!(binary.len() - body, 7 * 20);
+    }
+
+    #[test]
+    fn csv_and_xyz_rows() {
+        let mesh = Mesh { header: ScanHeader::new(1, 1), faces: Vec::new(), vertices: vec![Vertex {
+            position: [1.5, -2.0, 0.25],
+            line: 3,
+            point: 4,
+            distance: 500,
+        }] };
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &mesh).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "line,point,distance,x,y,z\n3,4,500,1.5,-2,0.25\n");
+
+        let mut xyz = Vec::new();
+        write_xyz(&mut xyz, &mesh).unwrap();
+        assert_eq!(String::from_utf8(xyz).unwrap(), "1.5 -2 0.25 3 4 500\n");
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use clap::{Parser, ValueEnum};
+
+use scan_export::mesh::{FaceFilter, Mesh};
+use scan_export::obj::write_obj;
+use scan_export::pcd::{write_pcd, PcdFormat};
+use scan_export::ply::{write_ply, PlyFormat};
+use scan_export::geometry::ScannerGeometry;
+use scan_export::stl::write_stl;
+use scan_export::table::{write_csv, write_xyz};
+use scan_format::ScanReader;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Ply,
+    Obj,
+    Stl,
+    Pcd,
+    Csv,
+    Xyz,
+}
+
+/// Converts a .rscan scan into a geometry file
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// Scan file written by the client
+    input: PathBuf,
+
+    /// Target file
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Output format, guessed from the output extension when omitted
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// Write the binary variant of PLY or PCD, STL is always binary
+    #[arg(long)]
+    binary: bool,
+
+    /// Write points only, without faces
+    #[arg(long)]
+    points_only: bool,
+
+    /// TOML calibration of the scanner geometry
+    #[arg(short, long)]
+    calibration: Option<PathBuf>,
+
+    /// Multiplier of the raw distance, overrides the calibration
+    #[arg(long)]
+    scale: Option<f64>,
+
+    /// Drop faces with an edge longer than this, in output units
+    #[arg(long)]
+    max_edge: Option<f32>,
+
+    /// Drop faces whose raw distances differ by more than this
+    #[arg(long)]
+    max_jump: Option<u32>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    let format = match args.format {
+        Some(format) => format,
+        None => match args.output.extension().and_then(|ext| ext.to_str()) {
+            Some("ply") => Format::Ply,
+            Some("obj") => Format::Obj,
+            Some("stl") => Format::Stl,
+            Some("pcd") => Format::Pcd,
+            Some("csv") => Format::Csv,
+            Some("xyz") => Format::Xyz,
+            _ => bail!("Cannot guess the format of {:?}, use --format", args.output),
+        },
+    };
+
+    // Checked before the output gets created, an existing file is not truncated then
+    match format {
+        Format::Stl if args.points_only => bail!("STL cannot store a point cloud"),
+        Format::Obj | Format::Csv | Format::Xyz if args.binary => bail!("{:?} has no binary variant", format),
+        _ => (),
+    }
+
+    let reader = ScanReader::open(&args.input).with_context(|| format!("Cannot open {:?}", args.input))?;
+    if !reader.header().is_finished() {
+        eprintln!("Warning: the scan has not been finished, exporting the received points");
+    }
+    let mut geometry = match &args.calibration {
+        Some(path) => ScannerGeometry::load(path).with_context(|| format!("Cannot load calibration {:?}", path))?,
+        None => ScannerGeometry::default(),
+    };
+    if let Some(scale) = args.scale {
+        geometry.distance_scale = scale;
+    }
+    let mut mesh = Mesh::from_scan(reader, &geometry).with_context(|| format!("Cannot read {:?}", args.input))?;
+
+    let filter = FaceFilter { max_edge: args.max_edge, max_distance_jump: args.max_jump };
+    if filter != FaceFilter::default() {
+        let removed = mesh.filter_faces(&filter);
+        println!("Removed {} faces exceeding the limits", removed);
+    }
+
+    let mut out = BufWriter::new(File::create(&args.output).with_context(|| format!("Cannot create {:?}", args.output))?);
+    match format {
+        Format::Ply => {
+            let encoding = if args.binary { PlyFormat::BinaryLittleEndian } else { PlyFormat::Ascii };
+            write_ply(&mut out, &mesh, encoding, !args.points_only)?;
+        }
+        Format::Obj => write_obj(&mut out, &mesh, !args.points_only)?,
+        Format::Stl => write_stl(&mut out, &mesh)?,
+        Format::Pcd => {
+            let encoding = if args.binary { PcdFormat::Binary } else { PcdFormat::Ascii };
+            write_pcd(&mut out, &mesh, encoding)?;
+        }
+        Format::Csv => write_csv(&mut out, &mesh)?,
+        Format::Xyz => write_xyz(&mut out, &mesh)?,
+    }
+
+    out.flush()?;
+
+    // Point cloud formats do not store faces
+    let faces = match format {
+        Format::Ply | Format::Obj | Format::Stl if !args.points_only => mesh.faces.len(),
+        _ => 0,
+    };
+    println!("Exported {} points and {} faces to {:?}", mesh.vertices.len(), faces, args.output);
+    Ok(())
+}
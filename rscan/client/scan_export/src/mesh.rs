@@ -0,0 +1,134 @@
+use std::io::Read;
+
+use scan_format::{FormatError, ScanHeader, ScanReader};
+
+use super::geometry::ScannerGeometry;
+
+/// Projected measurement.
+///
+/// position - cartesian coordinates
+/// line, point - position in the scan grid
+/// distance - raw distance reported by the lidar
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub line: u16,
+    pub point: u16,
+    pub distance: u32,
+}
+
+/// Limits of the faces kept in the mesh, used to avoid bridging depth discontinuities.
+///
+/// max_edge - longest allowed edge of a face in output units
+/// max_distance_jump - biggest allowed difference of raw distances within a face
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FaceFilter {
+    pub max_edge: Option<f32>,
+    pub max_distance_jump: Option<u32>,
+}
+
+impl FaceFilter {
+    /// Checks if the face made of the provided vertices is within limits
+    pub fn accepts(&self, corners: &[&Vertex]) -> bool {
+        if let Some(max_edge) = self.max_edge {
+            for (i, a) in corners.iter().enumerate() {
+                let b = corners[(i + 1) % corners.len()];
+                if distance(&a.position, &b.position) > max_edge { return false; }
+            }
+        }
+        if let Some(max_jump) = self.max_distance_jump {
+            let min = corners.iter().map(|v| v.distance).min().unwrap_or(0);
+            let max = corners.iter().map(|v| v.distance).max().unwrap_or(0);
+            if max - min > max_jump { return false; }
+        }
+        true
+    }
+}
+
+/// Vertices of a scan in the grid order with the quads joining neighbouring points.
+///
+/// Quads are built the same way `blender/script.py` builds them, `[p, p + 1]` of a line
+/// joined with `[p + 1, p]` of the next line. Points are placed in the grid by the reader,
+/// so serpentine lines are stitched in the right direction. Quads touching a missing point are left out.
+pub struct Mesh {
+    pub header: ScanHeader,
+    pub vertices: Vec<Vertex>,
+    pub faces: Vec<[u32; 4]>,
+}
+
+impl Mesh {
+    /// Reads the whole scan and projects it
+    ///
+    /// reader - freshly opened scan
+    /// geometry - scanner model applied to every point, missing step angles are taken from the scan
+    pub fn from_scan<R: Read>(mut reader: ScanReader<R>, geometry: &ScannerGeometry) -> Result<Self, FormatError> {
+        let header = reader.header().clone();
+        let geometry = geometry.with_header(&header);
+        let lines = header.number_of_lines as usize;
+        let points = header.number_of_points as usize;
+
+        // Grid cells, the later measurement of the same position wins, missing points leave holes
+        let mut grid: Vec<Option<Vertex>> = vec![None; lines * points];
+        for point in reader.by_ref() {
+            let point = point?;
+            let (line, idx) = (point.line as usize, point.point as usize);
+            if line >= lines || idx >= points || point.is_missing() { continue; }
+
+            grid[line * points + idx] = Some(Vertex {
+                position: geometry.project(&point),
+                line: point.line,
+                point: point.point,
+                distance: point.distance,
+            });
+        }
+
+        // Vertex index of every grid cell
+        let mut indices: Vec<Option<u32>> = vec![None; grid.len()];
+        let mut vertices = Vec::new();
+        for (cell, vertex) in grid.iter().enumerate() {
+            if let Some(vertex) = vertex {
+                indices[cell] = Some(vertices.len() as u32);
+                vertices.push(*vertex);
+            }
+        }
+
+        let mut faces = Vec::new();
+        for line in 0..lines.saturating_sub(1) {
+            for point in 0..points.saturating_sub(1) {
+                let quad = [
+                    indices[line * points + point],
+                    indices[line * points + point + 1],
+                    indices[(line + 1) * points + point + 1],
+                    indices[(line + 1) * points + point],
+                ];
+                if let [Some(a), Some(b), Some(c), Some(d)] = quad {
+                    faces.push([a, b, c, d]);
+                }
+            }
+        }
+
+        Ok(Mesh { header, vertices, faces })
+    }
+
+    /// Removes faces exceeding the filter limits
+    ///
+    /// @ret usize - number of removed faces
+    pub fn filter_faces(&mut self, filter: &FaceFilter) -> usize {
+        let before = self.faces.len();
+        let vertices = &self.vertices;
+        self.faces.retain(|face| {
+            let corners = face.map(|idx| &vertices[idx as usize]);
+            filter.accepts(&corners)
+        });
+        before - self.faces.len()
+    }
+
+    /// Faces split into triangles, `[a, b, c, d]` becomes `[a, b, c]` and `[a, c, d]`
+    pub fn triangles(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.faces.iter().flat_map(|&[a, b, c, d]| [[a, b, c], [a, c, d]])
+    }
+}
+
+fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+}
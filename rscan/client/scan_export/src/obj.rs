@@ -0,0 +1,23 @@
+use std::io::Write;
+
+use super::mesh::Mesh;
+
+/// Writes the mesh as a Wavefront OBJ file with triangulated faces
+///
+/// out - target writer
+/// mesh - projected scan
+/// faces - write faces, point cloud only otherwise
+pub fn write_obj<W: Write>(out: &mut W, mesh: &Mesh, faces: bool) -> std::io::Result<()> {
+    writeln!(out, "# rscan lines {} points {}", mesh.header.number_of_lines, mesh.header.number_of_points)?;
+    writeln!(out, "o scan")?;
+    for v in &mesh.vertices {
+        writeln!(out, "v {} {} {}", v.position[0], v.position[1], v.position[2])?;
+    }
+    if faces {
+        // OBJ indices start at 1
+        for [a, b, c] in mesh.triangles() {
+            writeln!(out, "f {} {} {}", a + 1, b + 1, c + 1)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::io::Write;
+
+use super::mesh::Mesh;
+
+/// Encoding of the PCD body.
+///
+/// Ascii - human readable
+/// Binary - packed little endian records
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PcdFormat {
+    Ascii,
+    Binary,
+}
+
+/// Writes the vertices as an unorganized PCL point cloud
+///
+/// out - target writer
+/// mesh - projected scan
+/// format - encoding of the body
+pub fn write_pcd<W: Write>(out: &mut W, mesh: &Mesh, format: PcdFormat) -> std::io::Result<()> {
+    let count = mesh.vertices.len();
+
+    writeln!(out, "# .PCD v0.7 - rscan lines {} points {}", mesh.header.number_of_lines, mesh.header.number_of_points)?;
+    writeln!(out, "VERSION 0.7")?;
+    writeln!(out, "FIELDS x y z line point distance")?;
+    writeln!(out, "SIZE 4 4 4 2 2 4")?;
+    writeln!(out, "TYPE F F F U U U")?;
+    writeln!(out, "COUNT 1 1 1 1 1 1")?;
+    writeln!(out, "WIDTH {}", count)?;
+    writeln!(out, "HEIGHT 1")?;
+    writeln!(out, "VIEWPOINT 0 0 0 1 0 0 0")?;
+    writeln!(out, "POINTS {}", count)?;
+
+    match format {
+        PcdFormat::Ascii => {
+            writeln!(out, "DATA ascii")?;
+            for v in &mesh.vertices {
+                writeln!(out, "{} {} {} {} {} {}", v.position[0], v.position[1], v.position[2], v.line, v.point, v.distance)?;
+            }
+        }
+        PcdFormat::Binary => {
+            writeln!(out, "DATA binary")?;
+            for v in &mesh.vertices {
+                for coord in v.position {
+                    out.write_all(&coord.to_le_bytes())?;
+                }
+                out.write_all(&v.line.to_le_bytes())?;
+                out.write_all(&v.point.to_le_bytes())?;
+                out.write_all(&v.distance.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::io::Write;
+
+use super::mesh::Mesh;
+
+/// Encoding of the PLY body.
+///
+/// Ascii - human readable
+/// BinaryLittleEndian - compact binary body
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+/// Writes the mesh as a PLY file
+///
+/// out - target writer
+/// mesh - projected scan
+/// format - encoding of the body
+/// faces - write quads, point cloud only otherwise
+pub fn write_ply<W: Write>(out: &mut W, mesh: &Mesh, format: PlyFormat, faces: bool) -> std::io::Result<()> {
+    let face_count = if faces { mesh.faces.len() } else { 0 };
+
+    writeln!(out, "ply")?;
+    match format {
+        PlyFormat::Ascii => writeln!(out, "format ascii 1.0")?,
+        PlyFormat::BinaryLittleEndian => writeln!(out, "format binary_little_endian 1.0")?,
+    }
+    writeln!(out, "comment rscan lines {} points {}", mesh.header.number_of_lines, mesh.header.number_of_points)?;
+    writeln!(out, "element vertex {}", mesh.vertices.len())?;
+    writeln!(out, "property float x")?;
+    writeln!(out, "property float y")?;
+    writeln!(out, "property float z")?;
+    writeln!(out, "property ushort line")?;
+    writeln!(out, "property ushort point")?;
+    writeln!(out, "property uint distance")?;
+    if faces {
+        writeln!(out, "element face {}", face_count)?;
+        writeln!(out, "property list uchar uint vertex_indices")?;
+    }
+    writeln!(out, "end_header")?;
+
+    match format {
+        PlyFormat::Ascii => {
+            for v in &mesh.vertices {
+                writeln!(out, "{} {} {} {} {} {}", v.position[0], v.position[1], v.position[2], v.line, v.point, v.distance)?;
+            }
+            for face in mesh.faces.iter().take(face_count) {
+                writeln!(out, "4 {} {} {} {}", face[0], face[1], face[2], face[3])?;
+            }
+        }
+        PlyFormat::BinaryLittleEndian => {
+            for v in &mesh.vertices {
+                for coord in v.position {
+                    out.write_all(&coord.to_le_bytes())?;
+                }
+                out.write_all(&v.line.to_le_bytes())?;
+                out.write_all(&v.point.to_le_bytes())?;
+                out.write_all(&v.distance.to_le_bytes())?;
+            }
+            for face in mesh.faces.iter().take(face_count) {
+                out.write_all(&[4])?;
+                for idx in face {
+                    out.write_all(&idx.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
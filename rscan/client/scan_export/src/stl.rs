@@ -0,0 +1,35 @@
+use std::io::Write;
+
+use super::mesh::Mesh;
+
+/// Writes the triangulated mesh as a binary STL file
+///
+/// out - target writer
+/// mesh - projected scan
+pub fn write_stl<W: Write>(out: &mut W, mesh: &Mesh) -> std::io::Result<()> {
+    let mut header = [0u8; 80];
+    let title = b"rscan binary STL";
+    header[..title.len()].copy_from_slice(title);
+    out.write_all(&header)?;
+
+    out.write_all(&(mesh.faces.len() as u32 * 2).to_le_bytes())?;
+    for triangle in mesh.triangles() {
+        let [a, b, c] = triangle.map(|idx| mesh.vertices[idx as usize].position);
+        for value in normal(&a, &b, &c).iter().chain(&a).chain(&b).chain(&c) {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        // Attribute byte count
+        out.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+/// Unit normal of the counter-clockwise triangle, zero for degenerate ones
+fn normal(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if len == 0.0 { return [0.0; 3]; }
+    [n[0] / len, n[1] / len, n[2] / len]
+}
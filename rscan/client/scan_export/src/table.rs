@@ -0,0 +1,26 @@
+use std::io::Write;
+
+use super::mesh::Mesh;
+
+/// Writes the vertices as CSV with a header row
+///
+/// out - target writer
+/// mesh - projected scan
+pub fn write_csv<W: Write>(out: &mut W, mesh: &Mesh) -> std::io::Result<()> {
+    writeln!(out, "line,point,distance,x,y,z")?;
+    for v in &mesh.vertices {
+        writeln!(out, "{},{},{},{},{},{}", v.line, v.point, v.distance, v.position[0], v.position[1], v.position[2])?;
+    }
+    Ok(())
+}
+
+/// Writes the vertices as space separated XYZ, coordinates first followed by the grid position and distance
+///
+/// out - target writer
+/// mesh - projected scan
+pub fn write_xyz<W: Write>(out: &mut W, mesh: &Mesh) -> std::io::Result<()> {
+    for v in &mesh.vertices {
+        writeln!(out, "{} {} {} {} {} {}", v.position[0], v.position[1], v.position[2], v.line, v.point, v.distance)?;
+    }
+    Ok(())
+}
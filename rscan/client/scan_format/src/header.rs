@@ -0,0 +1,227 @@
+use std::io::{Read, Write};
+
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+
+use super::FormatError;
+
+/// Magic bytes every scan file starts with
+pub const MAGIC: [u8; 4] = *b"RSCN";
+
+/// Revision of the file layout written by this crate
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Step angle of the 1.8 deg steppers, used when the device did not describe itself
+pub const DEFAULT_STEP_ANGLE: u32 = 1_800_000;
+
+/// Header flag bits.
+///
+/// FLAG_FINISHED - the scan has been closed by the writer, point count and Crc are valid
+/// FLAG_ABORTED - the scan has been aborted, the trailer follows the distances
+pub const FLAG_FINISHED: u16 = 0x0001;
+pub const FLAG_ABORTED: u16 = 0x0002;
+
+/// Distance stored in place of a point the device never delivered or failed to measure
+pub const MISSING_DISTANCE: u32 = u32::MAX;
+
+/// Magic bytes of the trailer closing an aborted scan, followed by the u32 index of the first missing point
+pub const TRAILER_MAGIC: [u8; 4] = *b"ABRT";
+
+/// Metadata stored at the beginning of a scan file.
+///
+/// flags - FLAG_* bits
+/// protocol_version - protocol version of the device
+/// fw_version - firmware major, minor and patch version
+/// number_of_points, number_of_lines - scan size as programmed with PROG
+/// point_step_size, line_step_size - steps between points and lines, counted in microsteps
+/// microsteps - microsteps per full step, 0 for the device default
+/// point_step_angle, line_step_angle - full step angle of the point (horizon) and line (azimuth) axis in microdegrees
+/// start_line, start_point - position of the first measurement
+/// started_at, finished_at - unix time in milliseconds, finished_at is 0 until the scan is finished
+/// point_count - number of distances stored after the header
+/// crc - Crc32 of the stored distances
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScanHeader {
+    pub flags: u16,
+    pub protocol_version: u8,
+    pub fw_version: [u8; 3],
+    pub number_of_points: u16,
+    pub number_of_lines: u16,
+    pub point_step_size: u16,
+    pub line_step_size: u16,
+    pub microsteps: u8,
+    pub point_step_angle: u32,
+    pub line_step_angle: u32,
+    pub start_line: u16,
+    pub start_point: u16,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub point_count: u32,
+    pub crc: u32,
+}
+
+impl ScanHeader {
+    /// Header of a new scan, device description set to defaults
+    ///
+    /// number_of_points - points in a line
+    /// number_of_lines - lines in the scan
+    pub fn new(number_of_points: u16, number_of_lines: u16) -> Self {
+        ScanHeader {
+            flags: 0,
+            protocol_version: 0,
+            fw_version: [0; 3],
+            number_of_points,
+            number_of_lines,
+            point_step_size: 1,
+            line_step_size: 1,
+            microsteps: 0,
+            point_step_angle: DEFAULT_STEP_ANGLE,
+            line_step_angle: DEFAULT_STEP_ANGLE,
+            start_line: 0,
+            start_point: 0,
+            started_at: 0,
+            finished_at: 0,
+            point_count: 0,
+            crc: 0,
+        }
+    }
+
+    /// Angle between two points of a line in degrees
+    pub fn point_angle(&self) -> f64 {
+        step_angle(self.point_step_angle, self.point_step_size, self.microsteps)
+    }
+
+    /// Angle between two lines in degrees
+    pub fn line_angle(&self) -> f64 {
+        step_angle(self.line_step_angle, self.line_step_size, self.microsteps)
+    }
+
+    /// Number of points the programmed scan should have
+    pub fn expected_points(&self) -> u32 {
+        self.number_of_points as u32 * self.number_of_lines as u32
+    }
+
+    /// Position of a measurement in the scan grid
+    ///
+    /// Lines are scanned in a serpentine, even lines go from the first point to the last one
+    /// and odd lines back. The first measurement belongs to start line and start point.
+    ///
+    /// index - measurement order of the distance
+    ///
+    /// @ret (u16, u16) - line and point of the measurement
+    pub fn position(&self, index: u32) -> (u16, u16) {
+        let points = self.number_of_points.max(1) as u64;
+        let start_offset = match self.start_line % 2 {
+            0 => self.start_point as u64,
+            _ => points - 1 - (self.start_point as u64).min(points - 1),
+        };
+        let path = self.start_line as u64 * points + start_offset + index as u64;
+
+        let line = path / points;
+        let offset = path % points;
+        let point = match line % 2 {
+            0 => offset,
+            _ => points - 1 - offset,
+        };
+        (line as u16, point as u16)
+    }
+
+    /// Measurement order of a position in the scan grid, the inverse of position
+    ///
+    /// line, point - position in the scan grid
+    ///
+    /// @ret Option<u32> - index of the measurement, None for positions outside of the grid or before the start
+    pub fn index_of(&self, line: u16, point: u16) -> Option<u32> {
+        if line >= self.number_of_lines || point >= self.number_of_points { return None; }
+
+        let points = self.number_of_points as u64;
+        let offset = |line: u16, point: u64| match line % 2 {
+            0 => point,
+            _ => points - 1 - point,
+        };
+        let start = self.start_line as u64 * points + offset(self.start_line, (self.start_point as u64).min(points - 1));
+        let path = line as u64 * points + offset(line, point as u64);
+        path.checked_sub(start).map(|index| index as u32)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.flags & FLAG_FINISHED != 0
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.flags & FLAG_ABORTED != 0
+    }
+
+    /// Serializes the header including magic and format version
+    /// out - a target writer
+    pub fn write_to<W: Write>(&self, out: &mut W) -> Result<(), FormatError> {
+        out.write_all(&MAGIC)?;
+        out.write_u16::<NetworkEndian>(FORMAT_VERSION)?;
+        out.write_u16::<NetworkEndian>(self.flags)?;
+        out.write_u8(self.protocol_version)?;
+        out.write_all(&self.fw_version)?;
+        out.write_u16::<NetworkEndian>(self.number_of_points)?;
+        out.write_u16::<NetworkEndian>(self.number_of_lines)?;
+        out.write_u16::<NetworkEndian>(self.point_step_size)?;
+        out.write_u16::<NetworkEndian>(self.line_step_size)?;
+        out.write_u8(self.microsteps)?;
+        // Reserved
+        out.write_u8(0)?;
+        out.write_u32::<NetworkEndian>(self.point_step_angle)?;
+        out.write_u32::<NetworkEndian>(self.line_step_angle)?;
+        out.write_u16::<NetworkEndian>(self.start_line)?;
+        out.write_u16::<NetworkEndian>(self.start_point)?;
+        out.write_u64::<NetworkEndian>(self.started_at)?;
+        out.write_u64::<NetworkEndian>(self.finished_at)?;
+        out.write_u32::<NetworkEndian>(self.point_count)?;
+        out.write_u32::<NetworkEndian>(self.crc)?;
+        Ok(())
+    }
+
+    /// Deserializes the header, checks magic and format version
+    /// input - reader placed at the start of the file
+    pub fn read_from<R: Read>(input: &mut R) -> Result<Self, FormatError> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC { return Err(FormatError::BadMagic); }
+
+        let version = input.read_u16::<NetworkEndian>()?;
+        if version != FORMAT_VERSION { return Err(FormatError::UnsupportedVersion(version)); }
+
+        let flags = input.read_u16::<NetworkEndian>()?;
+        let protocol_version = input.read_u8()?;
+        let mut fw_version = [0u8; 3];
+        input.read_exact(&mut fw_version)?;
+        let number_of_points = input.read_u16::<NetworkEndian>()?;
+        let number_of_lines = input.read_u16::<NetworkEndian>()?;
+        let point_step_size = input.read_u16::<NetworkEndian>()?;
+        let line_step_size = input.read_u16::<NetworkEndian>()?;
+        let microsteps = input.read_u8()?;
+        let _reserved = input.read_u8()?;
+
+        Ok(ScanHeader {
+            flags,
+            protocol_version,
+            fw_version,
+            number_of_points,
+            number_of_lines,
+            point_step_size,
+            line_step_size,
+            microsteps,
+            point_step_angle: input.read_u32::<NetworkEndian>()?,
+            line_step_angle: input.read_u32::<NetworkEndian>()?,
+            start_line: input.read_u16::<NetworkEndian>()?,
+            start_point: input.read_u16::<NetworkEndian>()?,
+            started_at: input.read_u64::<NetworkEndian>()?,
+            finished_at: input.read_u64::<NetworkEndian>()?,
+            point_count: input.read_u32::<NetworkEndian>()?,
+            crc: input.read_u32::<NetworkEndian>()?,
+        })
+    }
+
+    /// Size of the serialized header in bytes
+    pub const fn size_of() -> usize { 58 }
+}
+
+fn step_angle(full_step: u32, step_size: u16, microsteps: u8) -> f64 {
+    full_step as f64 * 1e-6 * step_size as f64 / microsteps.max(1) as f64
+}
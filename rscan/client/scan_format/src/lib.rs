@@ -0,0 +1,280 @@
+//! `.rscan` scan file format.
+//!
+//! A file consists of a fixed size big-endian `ScanHeader` followed by `point_count`
+//! big-endian u32 distances in the order they have been measured. Points the device did not deliver
+//! hold `MISSING_DISTANCE`. Aborted scans end with a trailer telling where the scan stopped.
+
+use std::io::Read;
+
+mod header;
+mod reader;
+mod writer;
+
+pub use header::{ScanHeader, DEFAULT_STEP_ANGLE, FLAG_ABORTED, FLAG_FINISHED, FORMAT_VERSION, MAGIC, MISSING_DISTANCE, TRAILER_MAGIC};
+pub use reader::{ScanPoint, ScanReader};
+pub use writer::ScanWriter;
+
+pub(crate) const CRC_CALC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// Error type of the scan file reading and writing.
+///
+/// Io - the underlying file failed
+/// BadMagic - the file is not a scan file
+/// UnsupportedVersion - the file layout revision is not known
+/// CrcMismatch - stored distances are damaged, expected is the stored Crc and got is the calculated one
+/// PointCountMismatch - the file holds other number of distances than the header declares
+/// BadTrailer - the trailer of an aborted scan is missing or does not match the point count
+/// Complete - the scan has been finished, there is nothing to resume
+///
+#[derive(Debug)]
+pub enum FormatError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u16),
+    CrcMismatch { expected: u32, got: u32 },
+    PointCountMismatch { expected: u32, got: u32 },
+    BadTrailer,
+    Complete,
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Io(e) => write!(f, "io error: {}", e),
+            FormatError::BadMagic => write!(f, "not a scan file"),
+            FormatError::UnsupportedVersion(version) => write!(f, "unsupported scan file version {}, expected {}", version, FORMAT_VERSION),
+            FormatError::CrcMismatch { expected, got } => write!(f, "Crc mismatch, expected {:#010x}, got {:#010x}", expected, got),
+            FormatError::PointCountMismatch { expected, got } => write!(f, "point count mismatch, expected {}, got {}", expected, got),
+            FormatError::BadTrailer => write!(f, "aborted scan trailer damaged"),
+            FormatError::Complete => write!(f, "scan already complete"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for FormatError {
+    fn from(e: std::io::Error) -> Self {
+        FormatError::Io(e)
+    }
+}
+
+/// Reads the whole scan, validates point count and Crc of finished scans
+///
+/// input - reader placed at the start of the file
+///
+/// @ret Result<(ScanHeader, Vec<u32>), FormatError> - header and distances in the measurement order
+pub fn read_distances<R: Read>(input: &mut R) -> Result<(ScanHeader, Vec<u32>), FormatError> {
+    let mut reader = ScanReader::new(input)?;
+    let distances = reader.by_ref()
+        .map(|point| point.map(|point| point.distance))
+        .collect::<Result<Vec<u32>, FormatError>>()?;
+
+    Ok((reader.header().clone(), distances))
+}
+
+/// Current unix time in milliseconds
+pub fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|t| t.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn sample_scan() -> Vec<u8> {
+        let mut header = ScanHeader::new(3, 2);
+        header.fw_version = [0, 1, 0];
+        header.point_step_size = 2;
+        header.microsteps = 16;
+
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), header).unwrap();
+        for distance in [1000, 1001, 1002, 1012, 1011, 1010] {
+            writer.push(distance).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn write_read_roundtrip() {
+        let file = sample_scan();
+        assert_eq!(file.len(), ScanHeader::size_of() + 6 * 4);
+        assert_eq!(file[0..4], MAGIC);
+
+        let (header, distances) = read_distances(&mut Cursor::new(file)).unwrap();
+
+        assert!(header.is_finished());
+        assert_eq!(header.point_count, 6);
+        assert_eq!(header.fw_version, [0, 1, 0]);
+        assert_eq!(header.expected_points(), 6);
+        assert!((header.point_angle() - 0.225).abs() < 1e-9);
+        assert!((header.line_angle() - 0.1125).abs() < 1e-9);
+        assert_eq!(distances, vec![1000, 1001, 1002, 1012, 1011, 1010]);
+    }
+
+    #[test]
+    fn rejects_damaged_files() {
+        let mut file = sample_scan();
+        let last = file.len() - 1;
+        file[last] ^= 0x01;
+        assert!(matches!(read_distances(&mut Cursor::new(&file)), Err(FormatError::CrcMismatch { .. })));
+
+        file[0] = b'X';
+        assert!(matches!(read_distances(&mut Cursor::new(&file)), Err(FormatError::BadMagic)));
+    }
+
+    #[test]
+    fn interrupted_scan_is_readable() {
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), ScanHeader::new(3, 2)).unwrap();
+        writer.header_mut().start_line = 1;
+        writer.push(1000).unwrap();
+        writer.sync_header().unwrap();
+        writer.push(1001).unwrap();
+
+        // Scan interrupted without finishing
+        let file = writer.into_inner().into_inner();
+        let (header, distances) = read_distances(&mut Cursor::new(file)).unwrap();
+
+        assert!(!header.is_finished());
+        assert_eq!(header.start_line, 1);
+        assert_eq!(header.point_count, 1);
+        assert_eq!(distances, vec![1000, 1001]);
+    }
+
+    #[test]
+    fn new_scan_drops_copied_flags() {
+        let mut header = ScanHeader::new(3, 2);
+        header.flags = FLAG_FINISHED | FLAG_ABORTED;
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), header.clone()).unwrap();
+        assert!(!writer.header().is_finished() && !writer.header().is_aborted());
+
+        writer.push(1000).unwrap();
+        writer.replace_header(header).unwrap();
+        assert!(!writer.header().is_finished() && !writer.header().is_aborted());
+        assert_eq!(writer.header().point_count, 1);
+
+        let (header, distances) = read_distances(&mut Cursor::new(writer.into_inner().into_inner())).unwrap();
+        assert!(!header.is_finished());
+        assert_eq!(distances, vec![1000]);
+    }
+
+    #[test]
+    fn aborted_scan_keeps_trailer() {
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), ScanHeader::new(3, 2)).unwrap();
+        for distance in [1000, 1001, 1002, 1012] {
+            writer.push(distance).unwrap();
+        }
+        let file = writer.abort().unwrap().into_inner();
+        assert_eq!(file.len(), ScanHeader::size_of() + 4 * 4 + 8);
+        assert_eq!(file[file.len() - 8..file.len() - 4], TRAILER_MAGIC);
+
+        let mut reader = ScanReader::new(Cursor::new(&file)).unwrap();
+        assert!(reader.header().is_finished());
+        assert_eq!(reader.aborted_at(), Some(4));
+        let distances: Vec<u32> = reader.by_ref().map(|point| point.unwrap().distance).collect();
+        assert_eq!(distances, vec![1000, 1001, 1002, 1012]);
+
+        // Trailer cut off
+        let truncated = &file[..file.len() - 8];
+        assert!(matches!(read_distances(&mut Cursor::new(truncated)), Err(FormatError::BadTrailer)));
+    }
+
+    #[test]
+    fn resumed_scan_continues() {
+        let path = std::env::temp_dir().join(format!("scan_format_resume_{}.rscan", std::process::id()));
+        let mut writer = ScanWriter::create(&path, ScanHeader::new(3, 2)).unwrap();
+        for distance in [1000, 1001, 1002] {
+            writer.push(distance).unwrap();
+        }
+        writer.abort().unwrap();
+
+        let mut writer = ScanWriter::resume(&path).unwrap();
+        assert_eq!(writer.header().point_count, 3);
+        assert!(!writer.header().is_finished());
+        for distance in [1012, 1011, 1010] {
+            writer.push(distance).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let (header, distances) = read_distances(&mut std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(header.is_finished() && !header.is_aborted());
+        assert_eq!(distances, vec![1000, 1001, 1002, 1012, 1011, 1010]);
+        assert!(matches!(ScanWriter::resume(&path), Err(FormatError::Complete)));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reader_undoes_serpentine() {
+        let file = sample_scan();
+        let mut reader = ScanReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.header().number_of_points, 3);
+
+        let points = reader.by_ref().collect::<Result<Vec<ScanPoint>, FormatError>>().unwrap();
+        let grid: Vec<(u16, u16, u32)> = points.iter().map(|p| (p.line, p.point, p.distance)).collect();
+
+        assert_eq!(grid, vec![(0, 0, 1000), (0, 1, 1001), (0, 2, 1002), (1, 2, 1012), (1, 1, 1011), (1, 0, 1010)]);
+        assert_eq!(reader.points_read(), 6);
+    }
+
+    #[test]
+    fn position_honours_start() {
+        let mut header = ScanHeader::new(4, 3);
+        header.start_line = 1;
+        header.start_point = 2;
+
+        // Odd line runs backwards, the scan continues with the next line forward
+        assert_eq!(header.position(0), (1, 2));
+        assert_eq!(header.position(2), (1, 0));
+        assert_eq!(header.position(3), (2, 0));
+        assert_eq!(header.position(6), (2, 3));
+
+        for index in 0..=6 {
+            let (line, point) = header.position(index);
+            assert_eq!(header.index_of(line, point), Some(index));
+        }
+        // Before the start and outside of the grid
+        assert_eq!(header.index_of(1, 3), None);
+        assert_eq!(header.index_of(0, 0), None);
+        assert_eq!(header.index_of(3, 0), None);
+    }
+
+    #[test]
+    fn missing_points_keep_their_place() {
+        let mut writer = ScanWriter::new(Cursor::new(Vec::new()), ScanHeader::new(3, 2)).unwrap();
+        writer.push(1000).unwrap();
+        writer.push_missing().unwrap();
+        writer.push(1002).unwrap();
+        let file = writer.finish().unwrap().into_inner();
+
+        let points = ScanReader::new(Cursor::new(file)).unwrap().collect::<Result<Vec<ScanPoint>, FormatError>>().unwrap();
+        let missing: Vec<bool> = points.iter().map(|point| point.is_missing()).collect();
+        assert_eq!(missing, vec![false, true, false]);
+        assert_eq!((points[2].line, points[2].point, points[2].distance), (0, 2, 1002));
+    }
+
+    #[test]
+    fn reader_reports_damage_at_the_end() {
+        let mut file = sample_scan();
+        let last = file.len() - 1;
+        file[last] ^= 0x01;
+
+        let results: Vec<Result<ScanPoint, FormatError>> = ScanReader::new(Cursor::new(file)).unwrap().collect();
+
+        assert_eq!(results.len(), 7);
+        assert!(results[..6].iter().all(|point| point.is_ok()));
+        assert!(matches!(results[6], Err(FormatError::CrcMismatch { .. })));
+    }
+}
@@ -0,0 +1,128 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use byteorder::{ByteOrder, NetworkEndian, ReadBytesExt};
+
+use super::header::{ScanHeader, MISSING_DISTANCE, TRAILER_MAGIC};
+use super::{FormatError, CRC_CALC};
+
+/// A single measurement placed in the scan grid.
+///
+/// line - line index, counted from the first line of the grid
+/// point - point index within the line, counted from the first point regardless of the scan direction
+/// distance - raw distance reported by the lidar, MISSING_DISTANCE if the point has not been measured
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanPoint {
+    pub line: u16,
+    pub point: u16,
+    pub distance: u32,
+}
+
+impl ScanPoint {
+    /// The point holds no measurement
+    pub fn is_missing(&self) -> bool {
+        self.distance == MISSING_DISTANCE
+    }
+}
+
+/// Reads a scan file point by point.
+///
+/// Iterates over the stored distances in the measurement order and places them in the grid,
+/// undoing the serpentine direction of odd lines. Finished scans are validated against the
+/// stored point count and Crc once the last point has been read, aborted ones against their trailer too.
+pub struct ScanReader<R: Read> {
+    inner: R,
+    header: ScanHeader,
+    digest: crc::Digest<'static, u32>,
+    index: u32,
+    done: bool,
+}
+
+impl ScanReader<BufReader<File>> {
+    /// Opens the scan file and reads its header
+    ///
+    /// path - scan file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, FormatError> {
+        ScanReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> ScanReader<R> {
+    /// Reads the header from the provided reader
+    ///
+    /// inner - reader placed at the start of the file
+    pub fn new(mut inner: R) -> Result<Self, FormatError> {
+        let header = ScanHeader::read_from(&mut inner)?;
+        Ok(ScanReader { inner, header, digest: CRC_CALC.digest(), index: 0, done: false })
+    }
+
+    pub fn header(&self) -> &ScanHeader {
+        &self.header
+    }
+
+    /// Number of points read so far
+    pub fn points_read(&self) -> u32 {
+        self.index
+    }
+
+    /// Index of the first point the aborted scan is missing
+    pub fn aborted_at(&self) -> Option<u32> {
+        self.header.is_aborted().then_some(self.header.point_count)
+    }
+
+    /// Reads the trailer of an aborted scan placed after the last distance
+    fn read_trailer(&mut self) -> Result<(), FormatError> {
+        let mut trailer = [0u8; 8];
+        match self.inner.read_exact(&mut trailer) {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(FormatError::BadTrailer),
+            Err(e) => return Err(e.into()),
+            Ok(()) => (),
+        }
+        let aborted_at = NetworkEndian::read_u32(&trailer[4..8]);
+        if trailer[0..4] != TRAILER_MAGIC || aborted_at != self.index { return Err(FormatError::BadTrailer); }
+        Ok(())
+    }
+
+    /// Checks the stored point count and Crc of finished scans
+    fn validate(&mut self) -> Result<(), FormatError> {
+        if !self.header.is_finished() { return Ok(()); }
+
+        if self.header.point_count != self.index {
+            return Err(FormatError::PointCountMismatch { expected: self.header.point_count, got: self.index });
+        }
+        let crc = self.digest.clone().finalize();
+        if self.header.crc != crc { return Err(FormatError::CrcMismatch { expected: self.header.crc, got: crc }); }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for ScanReader<R> {
+    type Item = Result<ScanPoint, FormatError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done { return None; }
+        if self.header.is_aborted() && self.index == self.header.point_count {
+            self.done = true;
+            return self.read_trailer().and_then(|_| self.validate()).err().map(Err);
+        }
+
+        match self.inner.read_u32::<NetworkEndian>() {
+            Ok(distance) => {
+                self.digest.update(&distance.to_be_bytes());
+                let (line, point) = self.header.position(self.index);
+                self.index += 1;
+                Some(Ok(ScanPoint { line, point, distance }))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                self.done = true;
+                self.validate().err().map(Err)
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+}
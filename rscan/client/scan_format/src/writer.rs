@@ -0,0 +1,148 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use byteorder::{NetworkEndian, WriteBytesExt};
+
+use super::header::{ScanHeader, FLAG_ABORTED, FLAG_FINISHED, MISSING_DISTANCE, TRAILER_MAGIC};
+use super::reader::ScanReader;
+use super::{unix_time_ms, FormatError, CRC_CALC};
+
+/// Streams distances into a scan file.
+///
+/// The header is written upfront with zero point count and gets rewritten by `finish`,
+/// so a file of an interrupted scan still starts with a valid, unfinished header.
+pub struct ScanWriter<W: Write + Seek> {
+    inner: W,
+    header: ScanHeader,
+    digest: crc::Digest<'static, u32>,
+}
+
+impl ScanWriter<File> {
+    /// Creates the scan file, truncating an existing one
+    ///
+    /// path - target file
+    /// header - scan parameters, point count and Crc are filled by the writer
+    pub fn create<P: AsRef<Path>>(path: P, header: ScanHeader) -> Result<Self, FormatError> {
+        ScanWriter::new(File::create(path)?, header)
+    }
+
+    /// Reopens the file of an interrupted or aborted scan to append the missing points
+    ///
+    /// The stored points are validated, the abort trailer is dropped and the scan is unfinished again.
+    ///
+    /// path - scan file
+    pub fn resume<P: AsRef<Path>>(path: P) -> Result<Self, FormatError> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut reader = ScanReader::new(BufReader::new(&mut file))?;
+        if reader.header().is_finished() && !reader.header().is_aborted() { return Err(FormatError::Complete); }
+        let mut digest = CRC_CALC.digest();
+        for point in reader.by_ref() {
+            digest.update(&point?.distance.to_be_bytes());
+        }
+        let mut header = reader.header().clone();
+        let point_count = reader.points_read();
+        drop(reader);
+
+        header.flags &= !(FLAG_FINISHED | FLAG_ABORTED);
+        header.point_count = point_count;
+        header.finished_at = 0;
+        header.crc = 0;
+        // Drops the trailer and a distance written only in part
+        file.set_len((ScanHeader::size_of() + point_count as usize * 4) as u64)?;
+
+        let mut writer = ScanWriter { inner: file, header, digest };
+        writer.sync_header()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Seek> ScanWriter<W> {
+    /// Writes the header to the start of the provided writer
+    ///
+    /// inner - target writer
+    /// header - scan parameters, point count and Crc are filled by the writer
+    pub fn new(mut inner: W, mut header: ScanHeader) -> Result<Self, FormatError> {
+        // Only finish and abort mark the scan, a header copied from an old file must not
+        header.flags &= !(FLAG_FINISHED | FLAG_ABORTED);
+        header.point_count = 0;
+        header.crc = 0;
+        if header.started_at == 0 { header.started_at = unix_time_ms(); }
+
+        inner.seek(SeekFrom::Start(0))?;
+        header.write_to(&mut inner)?;
+
+        Ok(ScanWriter { inner, header, digest: CRC_CALC.digest() })
+    }
+
+    pub fn header(&self) -> &ScanHeader {
+        &self.header
+    }
+
+    /// Header fields changed here are stored by the next `sync_header` or `finish`
+    pub fn header_mut(&mut self) -> &mut ScanHeader {
+        &mut self.header
+    }
+
+    /// Replaces the scan parameters and stores them, point count, Crc and start time stay with the writer
+    ///
+    /// header - the scan as reported by the device
+    pub fn replace_header(&mut self, header: ScanHeader) -> Result<(), FormatError> {
+        let ScanHeader { started_at, point_count, .. } = self.header;
+        let flags = header.flags & !(FLAG_FINISHED | FLAG_ABORTED);
+        self.header = ScanHeader { flags, started_at, finished_at: 0, point_count, crc: 0, ..header };
+        self.sync_header()
+    }
+
+    /// Appends a single distance
+    pub fn push(&mut self, distance: u32) -> Result<(), FormatError> {
+        let bytes = distance.to_be_bytes();
+        self.inner.write_all(&bytes)?;
+        self.digest.update(&bytes);
+        self.header.point_count += 1;
+        Ok(())
+    }
+
+    /// Appends the marker of a point without measurement, keeping the following points in place
+    pub fn push_missing(&mut self) -> Result<(), FormatError> {
+        self.push(MISSING_DISTANCE)
+    }
+
+    /// Rewrites the header with the current point count without finishing the scan
+    pub fn sync_header(&mut self) -> Result<(), FormatError> {
+        self.inner.seek(SeekFrom::Start(0))?;
+        self.header.write_to(&mut self.inner)?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Marks the scan finished, stores point count, Crc and finish time
+    ///
+    /// @ret Result<W, FormatError> - the underlying writer
+    pub fn finish(mut self) -> Result<W, FormatError> {
+        self.header.flags |= FLAG_FINISHED;
+        self.header.finished_at = unix_time_ms();
+        self.header.crc = self.digest.clone().finalize();
+        self.sync_header()?;
+
+        Ok(self.inner)
+    }
+
+    /// Closes the scan cut short, the trailer records the index of the first missing point
+    ///
+    /// @ret Result<W, FormatError> - the underlying writer
+    pub fn abort(mut self) -> Result<W, FormatError> {
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.write_all(&TRAILER_MAGIC)?;
+        self.inner.write_u32::<NetworkEndian>(self.header.point_count)?;
+        self.header.flags |= FLAG_ABORTED;
+        self.finish()
+    }
+
+    /// Returns the underlying writer leaving the scan unfinished
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
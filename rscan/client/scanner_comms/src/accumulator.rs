@@ -0,0 +1,94 @@
+/// COBS frame end delimiter
+pub const FRAME_END: u8 = 0x00;
+
+/// Result of feeding a chunk of bytes into the FrameAccumulator.
+/// 
+/// Consumed - whole chunk has been consumed, the frame is not complete yet
+/// Overflow - the frame did not fit into the buffer and is dropped, bytes up to the next delimiter are skipped
+/// Frame - complete frame including the end delimiter
+/// 
+/// `remaining` holds the part of the chunk that has not been consumed yet and must be fed again.
+pub enum FeedResult<'a, 'b> {
+    Consumed,
+    Overflow { remaining: &'a [u8] },
+    Frame { frame: &'b [u8], remaining: &'a [u8] },
+}
+
+/// Fixed capacity accumulator splitting a byte stream into COBS frames.
+/// 
+/// N - capacity of the buffer including the end delimiter, frames longer than that are dropped
+pub struct FrameAccumulator<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    discarding: bool,
+    resync_count: u32,
+}
+
+impl<const N: usize> FrameAccumulator<N> {
+    pub const fn new() -> Self {
+        FrameAccumulator {
+            buf: [0; N],
+            len: 0,
+            discarding: false,
+            resync_count: 0,
+        }
+    }
+
+    /// Feeds a chunk of received bytes, stops at the first complete frame.
+    /// 
+    /// input - received bytes, of any length
+    /// 
+    /// @ret FeedResult - see FeedResult, call again with `remaining` until Consumed is returned
+    pub fn feed<'a, 'b>(&'b mut self, input: &'a [u8]) -> FeedResult<'a, 'b> {
+        for (i, &byte) in input.iter().enumerate() {
+            let remaining = &input[i + 1..];
+
+            if self.discarding {
+                // Skipping the rest of oversize frame
+                if byte == FRAME_END { self.discarding = false; }
+                continue;
+            }
+
+            if byte == FRAME_END && self.len == 0 {
+                // Empty frame, nothing to report
+                continue;
+            }
+
+            if self.len == N {
+                // Frame does not fit, drop it and resync on the next delimiter
+                self.len = 0;
+                self.discarding = byte != FRAME_END;
+                self.resync_count = self.resync_count.wrapping_add(1);
+                return FeedResult::Overflow { remaining };
+            }
+
+            self.buf[self.len] = byte;
+            self.len += 1;
+
+            if byte == FRAME_END {
+                let len = self.len;
+                self.len = 0;
+                return FeedResult::Frame { frame: &self.buf[..len], remaining };
+            }
+        }
+
+        FeedResult::Consumed
+    }
+
+    /// Drops partially received frame
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.discarding = false;
+    }
+
+    /// Number of oversize frames dropped so far
+    pub fn resync_count(&self) -> u32 {
+        self.resync_count
+    }
+}
+
+impl<const N: usize> Default for FrameAccumulator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
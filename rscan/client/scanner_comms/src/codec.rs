@@ -0,0 +1,56 @@
+extern crate std;
+
+use bytes::{Buf, BytesMut};
+use log::warn;
+use tokio_util::codec::Decoder;
+
+use crate::accumulator::{FeedResult, FrameAccumulator};
+use crate::packets::{AnyPacket, DecodeError, MAX_FRAME_SIZE};
+
+/// tokio_util Decoder splitting the serial stream into packets.
+/// 
+/// Every complete frame yields an item, frames that fail to decode are passed
+/// as the error so the caller can log why they have been dropped.
+pub struct PacketCodec {
+    acc: FrameAccumulator<MAX_FRAME_SIZE>,
+}
+
+impl PacketCodec {
+    pub fn new() -> Self {
+        PacketCodec { acc: FrameAccumulator::new() }
+    }
+
+    /// Number of oversize frames dropped so far
+    pub fn resync_count(&self) -> u32 {
+        self.acc.resync_count()
+    }
+}
+
+impl Default for PacketCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for PacketCodec {
+    type Item = Result<AnyPacket, DecodeError>;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        while !src.is_empty() {
+            let (consumed, item) = match self.acc.feed(src) {
+                FeedResult::Consumed => (src.len(), None),
+                FeedResult::Overflow { remaining } => {
+                    warn!("Oversize frame dropped, resync count {:?}", self.acc.resync_count());
+                    (src.len() - remaining.len(), None)
+                }
+                FeedResult::Frame { frame, remaining } => (src.len() - remaining.len(), Some(AnyPacket::decode(frame))),
+            };
+            src.advance(consumed);
+
+            if item.is_some() { return Ok(item); }
+        }
+
+        Ok(None)
+    }
+}
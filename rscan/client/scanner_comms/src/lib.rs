@@ -10,6 +10,10 @@ use panic_abort as _;
 //use panic_semihosting as _;
 
 pub mod packets;
+pub mod accumulator;
+pub mod transport;
+#[cfg(feature = "codec")]
+pub mod codec;
 
 //#[no_mangle]
 //pub extern "C" fn aaa(
@@ -34,11 +38,11 @@ mod tests {
         let mut buf: [u8; 20] = [0; 20];
         let buf_ptr = buf.as_mut_ptr();
 
-        let test_ok = packets::packet_ok::OkPacket::new(123, 0xa0, 0x0a);
+        let test_ok = packets::packet_ok::OkPacket::new(123, 122);
 
         let len = test_ok.serialize(buf_ptr, 20);
 
-        let mut rx_packet = packets::packet_ok::OkPacket::new(0, 0, 0);
+        let mut rx_packet = packets::packet_ok::OkPacket::new(0, 0);
 
         let _len = packets::packet_ok::OkPacket::deserialize(buf_ptr, len, &mut rx_packet);
 
@@ -85,7 +89,7 @@ mod tests {
         let mut buf: [u8; 20] = [0; 20];
         let buf_ptr = buf.as_mut_ptr();
 
-        let test_ok = packets::packet_fin::FinPacket::new(123, 15123);
+        let test_ok = packets::packet_fin::FinPacket::new(123, 180_000);
 
         let len = test_ok.serialize(buf_ptr, 20);
 
@@ -102,11 +106,11 @@ mod tests {
         let mut buf: [u8; 20] = [0; 20];
         let buf_ptr = buf.as_mut_ptr();
 
-        let test_ok = packets::packet_prog::ProgPacket::new(123, 42, 11);
+        let test_ok = packets::packet_prog::ProgPacket::new(123, 600, 300, 2, 1, 16);
 
         let len = test_ok.serialize(buf_ptr, 20);
 
-        let mut rx_packet = packets::packet_prog::ProgPacket::new(0, 0, 0);
+        let mut rx_packet = packets::packet_prog::ProgPacket::new(0, 0, 0, 0, 0, 0);
 
         let _len = packets::packet_prog::ProgPacket::deserialize(buf_ptr, len, &mut rx_packet);
 
@@ -119,11 +123,11 @@ mod tests {
         let mut buf: [u8; 20] = [0; 20];
         let buf_ptr = buf.as_mut_ptr();
 
-        let test_ok = packets::packet_mes::MesPacket::new(123, 67890);
+        let test_ok = packets::packet_mes::MesPacket::new(123, 4, 17, 67890, 1200, packets::MesStatus::Weak);
 
         let len = test_ok.serialize(buf_ptr, 20);
 
-        let mut rx_packet = packets::packet_mes::MesPacket::new(0, 0);
+        let mut rx_packet = packets::packet_mes::MesPacket::new(0, 0, 0, 0, 0, packets::MesStatus::Valid);
 
         let _len = packets::packet_mes::MesPacket::deserialize(buf_ptr, len, &mut rx_packet);
 
@@ -136,15 +140,392 @@ mod tests {
         let mut buf: [u8; 20] = [0; 20];
         let buf_ptr = buf.as_mut_ptr();
 
-        let test_ok = packets::packet_mov::MovPacket::new(123, Axis::Azimuth, RotSide::Clockwise, 32);
+        let test_ok = packets::packet_mov::MovPacket::new(123, Axis::Azimuth, -40_000);
 
         let len = test_ok.serialize(buf_ptr, 20);
 
-        let mut rx_packet = packets::packet_mov::MovPacket::new(0, Axis::Horizon, RotSide::CounterClockwise, 0);
+        let mut rx_packet = packets::packet_mov::MovPacket::new(0, Axis::Horizon, 0);
 
         let _len = packets::packet_mov::MovPacket::deserialize(buf_ptr, len, &mut rx_packet);
 
         assert_eq!(test_ok, rx_packet);
+        assert_eq!(rx_packet.side(), RotSide::CounterClockwise);
+    }
+
+    #[test]
+    fn start_serial_deserial() {
+
+        let mut buf: [u8; 20] = [0; 20];
+        let buf_ptr = buf.as_mut_ptr();
+
+        let test_ok = packets::packet_start::StartPacket::new(123, 3, 1017);
+
+        let len = test_ok.serialize(buf_ptr, 20);
+
+        let mut rx_packet = packets::packet_start::StartPacket::new(0, 0, 0);
+
+        let _len = packets::packet_start::StartPacket::deserialize(buf_ptr, len, &mut rx_packet);
+
+        assert_eq!(test_ok, rx_packet);
+    }
+
+    #[test]
+    fn info_serial_deserial() {
+
+        let mut buf: [u8; 30] = [0; 30];
+        let buf_ptr = buf.as_mut_ptr();
+
+        let test_ok = packets::packet_info::InfoPacket::new(123, 1, 2, 3, 1_800_000, 900_000, 400, packets::CAP_ABORT);
+
+        let len = test_ok.serialize(buf_ptr, 30);
+
+        let mut rx_packet = packets::packet_info::InfoPacket::new(0, 0, 0, 0, 0, 0, 0, 0);
+
+        let _len = packets::packet_info::InfoPacket::deserialize(buf_ptr, len, &mut rx_packet);
+
+        assert_eq!(test_ok, rx_packet);
+        assert!(rx_packet.supports(packets::CAP_ABORT));
+    }
+
+    #[test]
+    fn resume_serial_deserial() {
+
+        let mut buf: [u8; 30] = [0; 30];
+        let buf_ptr = buf.as_mut_ptr();
+
+        let test_ok = packets::packet_resume::ResumePacket::new(123, 60, 30, 2, 1, 4, 17, 41);
+
+        let len = test_ok.serialize(buf_ptr, 30);
+
+        let mut rx_packet = packets::packet_resume::ResumePacket::new(0, 0, 0, 0, 0, 0, 0, 0);
+
+        let _len = packets::packet_resume::ResumePacket::deserialize(buf_ptr, len, &mut rx_packet);
+
+        assert_eq!(test_ok, rx_packet);
+        assert!(matches!(packets::AnyPacket::decode(&test_ok.encode()), Ok(packets::AnyPacket::Resume(_))));
+    }
+
+    #[test]
+    fn ok_rejects_legacy_version() {
+
+        // Protocol version 1 OK: header followed by two sentinel bytes
+        let mut packet: [u8; 8] = [8, 0x00, 0x7b, 0x01, 0x00, 0x00, 0xa0, 0x0a];
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM).checksum(&packet);
+        packet[4..6].copy_from_slice(&crc.to_be_bytes());
+
+        let mut frame = [0u8; packets::MAX_FRAME_SIZE];
+        let len = corncobs::encode_buf(&packet, &mut frame);
+
+        assert_eq!(packets::packet_ok::OkPacket::decode(&frame[..len]), Err(packets::DecodeError::UnsupportedVersion(1)));
+        assert_eq!(packets::AnyPacket::decode(&frame[..len]), Err(packets::DecodeError::UnsupportedVersion(1)));
+
+        // Protocol version 2 OK has the current layout, but the previous PROG/MOV layout
+        let mut test_ok = packets::packet_ok::OkPacket::new(123, 122);
+        test_ok.version = 2;
+
+        assert_eq!(packets::AnyPacket::decode(&test_ok.encode()), Err(packets::DecodeError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn mes_encode_decode() {
+
+        let mut buf: [u8; packets::MAX_FRAME_SIZE] = [0; packets::MAX_FRAME_SIZE];
+
+        let test_mes = packets::packet_mes::MesPacket::new(123, 4, 17, 67890, 1200, packets::MesStatus::OutOfRange);
+
+        let len = test_mes.encode_into(&mut buf).unwrap();
+
+        let rx_packet = packets::packet_mes::MesPacket::decode(&buf[..len]).unwrap();
+
+        assert_eq!(test_mes, rx_packet);
+    }
+
+    #[test]
+    fn mes_rejects_bad_status() {
+
+        let test_mes = packets::packet_mes::MesPacket::new(123, 4, 17, 67890, 1200, packets::MesStatus::Valid);
+
+        // Status byte outside of the known values
+        let mut packet = [0u8; packets::packet_mes::MesPacket::size_of()];
+        let len = corncobs::decode_buf(&test_mes.encode(), &mut packet).unwrap();
+        assert_eq!(len, packet.len());
+        packet[len - 1] = 0x04;
+        packet[4..6].copy_from_slice(&[0, 0]);
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM).checksum(&packet);
+        packet[4..6].copy_from_slice(&crc.to_be_bytes());
+
+        let mut frame = [0u8; packets::MAX_FRAME_SIZE];
+        let len = corncobs::encode_buf(&packet, &mut frame);
+        assert_eq!(packets::packet_mes::MesPacket::decode(&frame[..len]), Err(packets::DecodeError::InvalidField));
+    }
+
+    #[test]
+    fn mes_batch_encode_decode() {
+
+        let mut test_batch = packets::packet_mes_batch::MesBatchPacket::new(123, 480);
+        assert!(test_batch.push(1000, 1200, packets::MesStatus::Valid));
+        assert!(test_batch.push(0, 0, packets::MesStatus::OutOfRange));
+        assert!(test_batch.push(1002, 80, packets::MesStatus::Weak));
+
+        // Only the carried measurements travel
+        let frame = test_batch.encode();
+        assert_eq!(frame.len(), packets::packet_mes_batch::MesBatchPacket::size_with(3) + 2);
+
+        let rx_packet = packets::packet_mes_batch::MesBatchPacket::decode(&frame).unwrap();
+        assert_eq!(rx_packet.points(), &[1000, 0, 1002]);
+        assert_eq!(rx_packet.strengths(), &[1200, 0, 80]);
+        assert_eq!(rx_packet.statuses(), &[packets::MesStatus::Valid, packets::MesStatus::OutOfRange, packets::MesStatus::Weak]);
+        assert_eq!(test_batch, rx_packet);
+        assert_eq!(packets::AnyPacket::decode(&frame), Ok(packets::AnyPacket::MesBatch(rx_packet)));
+
+        // Full batch refuses more points and still fits the frame
+        let mut full_batch = packets::packet_mes_batch::MesBatchPacket::new(124, 0);
+        while full_batch.push(7, 0, packets::MesStatus::Valid) {}
+        assert_eq!(full_batch.points().len(), packets::MES_BATCH_MAX_POINTS);
+        assert!(matches!(packets::AnyPacket::decode(&full_batch.encode()), Ok(packets::AnyPacket::MesBatch(_))));
+    }
+
+    #[test]
+    fn mes_batch_rejects_bad_count() {
+
+        // Count larger than the batch can hold
+        let mut packet = [0u8; packets::packet_mes_batch::MesBatchPacket::size_with(0)];
+        packet[0] = packet.len() as u8;
+        packet[3] = 0x0c;
+        packet[10] = packets::MES_BATCH_MAX_POINTS as u8 + 1;
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM).checksum(&packet);
+        packet[4..6].copy_from_slice(&crc.to_be_bytes());
+
+        let mut frame = [0u8; packets::MAX_FRAME_SIZE];
+        let len = corncobs::encode_buf(&packet, &mut frame);
+        assert_eq!(packets::AnyPacket::decode(&frame[..len]), Err(packets::DecodeError::InvalidField));
+
+        // Count not matching the length
+        packet[10] = 2;
+        packet[4..6].copy_from_slice(&[0, 0]);
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM).checksum(&packet);
+        packet[4..6].copy_from_slice(&crc.to_be_bytes());
+        let len = corncobs::encode_buf(&packet, &mut frame);
+        assert!(matches!(packets::AnyPacket::decode(&frame[..len]), Err(packets::DecodeError::LengthMismatch { .. })));
+
+        // Status byte outside of the known values
+        let mut batch = packets::packet_mes_batch::MesBatchPacket::new(123, 0);
+        batch.push(1000, 0, packets::MesStatus::Valid);
+        let mut packet = [0u8; packets::packet_mes_batch::MesBatchPacket::size_with(1)];
+        let len = corncobs::decode_buf(&batch.encode(), &mut packet).unwrap();
+        packet[len - 1] = 0x04;
+        packet[4..6].copy_from_slice(&[0, 0]);
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_XMODEM).checksum(&packet);
+        packet[4..6].copy_from_slice(&crc.to_be_bytes());
+        let len = corncobs::encode_buf(&packet, &mut frame);
+        assert_eq!(packets::AnyPacket::decode(&frame[..len]), Err(packets::DecodeError::InvalidField));
+    }
+
+    #[test]
+    fn encode_buffer_too_small() {
+
+        let mut buf: [u8; 4] = [0; 4];
+
+        let test_mov = packets::packet_mov::MovPacket::new(123, Axis::Azimuth, 32);
+
+        assert_eq!(test_mov.encode_into(&mut buf), Err(packets::EncodeError::BufferTooSmall));
+    }
+
+    #[test]
+    fn decode_rejects_broken_frames() {
+
+        let mut buf: [u8; packets::MAX_FRAME_SIZE] = [0; packets::MAX_FRAME_SIZE];
+
+        let test_mov = packets::packet_mov::MovPacket::new(123, Axis::Azimuth, 32);
+
+        let len = test_mov.encode_into(&mut buf).unwrap();
+
+        // Frame of other packet type with the same length
+        let test_start = packets::packet_start::StartPacket::new(123, 3, 17);
+        assert_eq!(packets::packet_fin::FinPacket::decode(&test_start.encode()), Err(packets::DecodeError::UnknownType(0x08)));
+
+        // Truncated frame
+        assert_eq!(packets::packet_mov::MovPacket::decode(&buf[..len - 1]), Err(packets::DecodeError::Truncated));
+
+        // Frame with trailing garbage
+        assert_eq!(packets::packet_mov::MovPacket::decode(&buf[..len + 1]), Err(packets::DecodeError::LengthMismatch { expected: len, got: len + 1 }));
+
+        // Flipped payload bit
+        buf[len - 2] ^= 0x01;
+        assert!(matches!(packets::packet_mov::MovPacket::decode(&buf[..len]), Err(packets::DecodeError::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn any_packet_dispatch() {
+
+        let test_fin = packets::packet_fin::FinPacket::new(123, 15123);
+
+        let frame = test_fin.encode();
+
+        let rx_packet = packets::AnyPacket::decode(&frame).unwrap();
+
+        assert_eq!(rx_packet.packet_type(), packets::PacketType::Fin);
+        assert_eq!(rx_packet, packets::AnyPacket::Fin(test_fin));
+    }
+
+    #[test]
+    fn any_packet_rejects_unknown_type() {
+
+        let test_abort = packets::packet_abort::AbortPacket::new(123);
+
+        let mut frame = [0u8; packets::MAX_FRAME_SIZE];
+        let len = test_abort.encode_into(&mut frame).unwrap();
+
+        // COBS keeps non-zero bytes in place, shifted by the leading code byte
+        frame[4] = 0x42;
+
+        assert_eq!(packets::AnyPacket::decode(&frame[..len]), Err(packets::DecodeError::UnknownType(0x42)));
+    }
+
+    #[test]
+    fn try_deserialize_status() {
+
+        let mut buf: [u8; packets::MAX_FRAME_SIZE] = [0; packets::MAX_FRAME_SIZE];
+
+        let test_prog = packets::packet_prog::ProgPacket::new(123, 42, 11, 1, 1, 0);
+
+        let len = test_prog.encode_into(&mut buf).unwrap();
+
+        let mut rx_packet = packets::packet_prog::ProgPacket::new(0, 0, 0, 0, 0, 0);
+
+        assert_eq!(packets::packet_prog::ProgPacket::try_deserialize(buf.as_ptr(), len, &mut rx_packet), packets::DecodeStatus::Ok);
+        assert_eq!(test_prog, rx_packet);
+
+        assert_eq!(packets::packet_prog::ProgPacket::try_deserialize(buf.as_ptr(), len - 2, &mut rx_packet), packets::DecodeStatus::Truncated);
+    }
+
+    #[test]
+    fn accumulator_split_chunks() {
+
+        let test_mes = packets::packet_mes::MesPacket::new(123, 4, 17, 67890, 1200, packets::MesStatus::Valid);
+        let test_fin = packets::packet_fin::FinPacket::new(124, 15123);
+
+        let mes_frame = test_mes.encode();
+        let fin_frame = test_fin.encode();
+
+        // Stream with MES split in half and FIN glued to its end
+        let mut stream = [0u8; 2 * packets::MAX_FRAME_SIZE];
+        stream[..mes_frame.len()].copy_from_slice(&mes_frame);
+        stream[mes_frame.len()..mes_frame.len() + fin_frame.len()].copy_from_slice(&fin_frame);
+        let stream = &stream[..mes_frame.len() + fin_frame.len()];
+        let (first, second) = stream.split_at(5);
+
+        let mut acc = accumulator::FrameAccumulator::<{ packets::MAX_FRAME_SIZE }>::new();
+
+        assert!(matches!(acc.feed(first), accumulator::FeedResult::Consumed));
+
+        let remaining = match acc.feed(second) {
+            accumulator::FeedResult::Frame { frame, remaining } => {
+                assert_eq!(packets::AnyPacket::decode(frame), Ok(packets::AnyPacket::Mes(test_mes)));
+                remaining
+            }
+            _ => panic!("MES frame expected"),
+        };
+
+        match acc.feed(remaining) {
+            accumulator::FeedResult::Frame { frame, remaining } => {
+                assert_eq!(packets::AnyPacket::decode(frame), Ok(packets::AnyPacket::Fin(test_fin)));
+                assert!(remaining.is_empty());
+            }
+            _ => panic!("FIN frame expected"),
+        }
+    }
+
+    #[test]
+    fn accumulator_resync_after_overflow() {
+
+        let test_abort = packets::packet_abort::AbortPacket::new(123);
+        let abort_frame = test_abort.encode();
+
+        let mut acc = accumulator::FrameAccumulator::<{ packets::MAX_FRAME_SIZE }>::new();
+
+        // Garbage without a delimiter overflows the buffer
+        let garbage = [0x55u8; packets::MAX_FRAME_SIZE + 3];
+        let remaining = match acc.feed(&garbage) {
+            accumulator::FeedResult::Overflow { remaining } => remaining,
+            _ => panic!("Overflow expected"),
+        };
+        assert_eq!(acc.resync_count(), 1);
+
+        // Rest of the garbage is skipped up to the delimiter
+        assert!(matches!(acc.feed(remaining), accumulator::FeedResult::Consumed));
+        assert!(matches!(acc.feed(&[0x55, accumulator::FRAME_END]), accumulator::FeedResult::Consumed));
+
+        match acc.feed(&abort_frame) {
+            accumulator::FeedResult::Frame { frame, .. } => {
+                assert_eq!(packets::AnyPacket::decode(frame), Ok(packets::AnyPacket::Abort(test_abort)));
+            }
+            _ => panic!("ABORT frame expected"),
+        }
+    }
+
+    #[test]
+    fn transport_retransmit_until_failure() {
+
+        let config = transport::TransportConfig { timeout_ms: 100, max_retries: 2 };
+        let mut sender = transport::ReliableSender::<2>::new(config);
+
+        let frame = packets::packet_prog::ProgPacket::new(7, 42, 11, 1, 1, 0).encode();
+
+        sender.track(7, &frame, 1000).unwrap();
+        assert_eq!(sender.track(7, &frame, 1000), Err(transport::TransportError::IdInUse));
+
+        assert_eq!(sender.poll(1099), None);
+        assert_eq!(sender.poll(1100), Some(transport::TransportEvent::Retransmit { id: 7, frame: &frame[..] }));
+        assert_eq!(sender.poll(1150), None);
+        assert_eq!(sender.poll(1200), Some(transport::TransportEvent::Retransmit { id: 7, frame: &frame[..] }));
+        assert_eq!(sender.poll(1300), Some(transport::TransportEvent::DeliveryFailed { id: 7 }));
+        assert_eq!(sender.pending(), 0);
+    }
+
+    #[test]
+    fn transport_ack_and_nack() {
+
+        let mut sender = transport::ReliableSender::<2>::new(transport::TransportConfig::default());
+
+        let frame = packets::packet_abort::AbortPacket::new(1).encode();
+
+        sender.track(1, &frame, u32::MAX - 10).unwrap();
+        sender.track(2, &frame, u32::MAX - 10).unwrap();
+        assert_eq!(sender.track(3, &frame, 0), Err(transport::TransportError::Full));
+
+        // ERR BROKEN triggers retransmission without waiting for the timeout
+        assert!(sender.request_retransmit(2));
+        assert_eq!(sender.poll(0), Some(transport::TransportEvent::Retransmit { id: 2, frame: &frame[..] }));
+
+        assert!(sender.acknowledge(1));
+        assert!(!sender.acknowledge(1));
+        assert!(sender.acknowledge(2));
+        assert_eq!(sender.poll(u32::MAX), None);
+    }
+
+    #[test]
+    fn duplicate_filter() {
+
+        let mut filter = transport::DuplicateFilter::<2>::new();
+
+        assert!(filter.check(10));
+        assert!(!filter.check(10));
+        assert!(filter.check(11));
+        assert!(filter.check(12));
+        // 10 is no longer remembered
+        assert!(filter.check(10));
+        assert!(!filter.check(12));
+    }
+
+    #[test]
+    fn id_allocator_wraps() {
+
+        let mut ids = transport::MessageIdAllocator::new(0xfffe);
+
+        assert_eq!(ids.next_id(), 0xfffe);
+        assert_eq!(ids.next_id(), 0xffff);
+        assert_eq!(ids.next_id(), 0x0000);
     }
     
 }
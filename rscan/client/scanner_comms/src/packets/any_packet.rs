@@ -0,0 +1,110 @@
+use super::header::Header;
+use super::packet_abort::AbortPacket;
+use super::packet_err::ErrPacket;
+use super::packet_fin::FinPacket;
+use super::packet_mes::MesPacket;
+use super::packet_mes_batch::MesBatchPacket;
+use super::packet_mov::MovPacket;
+use super::packet_ok::OkPacket;
+use super::packet_prog::ProgPacket;
+use super::packet_start::StartPacket;
+use super::packet_hello::HelloPacket;
+use super::packet_info::InfoPacket;
+use super::packet_resume::ResumePacket;
+use super::DecodeError;
+use super::PacketType;
+use super::MAX_FRAME_SIZE;
+
+
+
+/// Enum wrapping every packet that can be received from the link.
+/// 
+/// Used when the type of the incoming packet is not known upfront.
+// No allocator on the device, the batch cannot be boxed
+#[allow(clippy::large_enum_variant)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub enum AnyPacket {
+    Ok(OkPacket),
+    Err(ErrPacket),
+    Mov(MovPacket),
+    Mes(MesPacket),
+    MesBatch(MesBatchPacket),
+    Abort(AbortPacket),
+    Prog(ProgPacket),
+    Fin(FinPacket),
+    Start(StartPacket),
+    Hello(HelloPacket),
+    Info(InfoPacket),
+    Resume(ResumePacket),
+}
+
+impl AnyPacket {
+    /// Decodes a packet of any type from the COBS frame
+    /// 
+    /// frame - slice containing the framed packet including the end delimiter
+    /// 
+    /// @ret Result<AnyPacket, DecodeError> - the packet described by the header or the reason it has been rejected
+    pub fn decode(frame: &[u8]) -> Result<Self, DecodeError> {
+        if frame.len() > MAX_FRAME_SIZE { return Err(DecodeError::LengthMismatch { expected: MAX_FRAME_SIZE, got: frame.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; MAX_FRAME_SIZE] = [0; MAX_FRAME_SIZE];
+
+        let (header, payload) = super::unframe(frame, &mut tmp_buf)?;
+        AnyPacket::from_parts(header, payload)
+    }
+
+    fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        match header.packet_type {
+            PacketType::Ok => OkPacket::from_parts(header, payload).map(AnyPacket::Ok),
+            PacketType::Err => ErrPacket::from_parts(header, payload).map(AnyPacket::Err),
+            PacketType::Mov => MovPacket::from_parts(header, payload).map(AnyPacket::Mov),
+            PacketType::Mes => MesPacket::from_parts(header, payload).map(AnyPacket::Mes),
+            PacketType::MesBatch => MesBatchPacket::from_parts(header, payload).map(AnyPacket::MesBatch),
+            PacketType::Abord => AbortPacket::from_parts(header, payload).map(AnyPacket::Abort),
+            PacketType::Prog => ProgPacket::from_parts(header, payload).map(AnyPacket::Prog),
+            PacketType::Fin => FinPacket::from_parts(header, payload).map(AnyPacket::Fin),
+            PacketType::Start => StartPacket::from_parts(header, payload).map(AnyPacket::Start),
+            PacketType::Hello => HelloPacket::from_parts(header, payload).map(AnyPacket::Hello),
+            PacketType::Info => InfoPacket::from_parts(header, payload).map(AnyPacket::Info),
+            PacketType::Resume => ResumePacket::from_parts(header, payload).map(AnyPacket::Resume),
+            PacketType::Uknown => Err(DecodeError::UnknownType(PacketType::Uknown as u8)),
+        }
+    }
+
+    /// Type of the wrapped packet
+    pub fn packet_type(&self) -> PacketType {
+        match self {
+            AnyPacket::Ok(_) => PacketType::Ok,
+            AnyPacket::Err(_) => PacketType::Err,
+            AnyPacket::Mov(_) => PacketType::Mov,
+            AnyPacket::Mes(_) => PacketType::Mes,
+            AnyPacket::MesBatch(_) => PacketType::MesBatch,
+            AnyPacket::Abort(_) => PacketType::Abord,
+            AnyPacket::Prog(_) => PacketType::Prog,
+            AnyPacket::Fin(_) => PacketType::Fin,
+            AnyPacket::Start(_) => PacketType::Start,
+            AnyPacket::Hello(_) => PacketType::Hello,
+            AnyPacket::Info(_) => PacketType::Info,
+            AnyPacket::Resume(_) => PacketType::Resume,
+        }
+    }
+
+    /// Message ID of the wrapped packet
+    pub fn message_id(&self) -> u16 {
+        match self {
+            AnyPacket::Ok(pack) => pack.message_id(),
+            AnyPacket::Err(pack) => pack.message_id(),
+            AnyPacket::Mov(pack) => pack.message_id(),
+            AnyPacket::Mes(pack) => pack.message_id(),
+            AnyPacket::MesBatch(pack) => pack.message_id(),
+            AnyPacket::Abort(pack) => pack.message_id(),
+            AnyPacket::Prog(pack) => pack.message_id(),
+            AnyPacket::Fin(pack) => pack.message_id(),
+            AnyPacket::Start(pack) => pack.message_id(),
+            AnyPacket::Hello(pack) => pack.message_id(),
+            AnyPacket::Info(pack) => pack.message_id(),
+            AnyPacket::Resume(pack) => pack.message_id(),
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use super::PacketType;
-use super::ErrCode;
+use super::DecodeError;
 use super::CRC_CALC;
 
 use byteorder::ByteOrder;
@@ -51,15 +51,16 @@ impl Header {
     /// 
     /// input - slice containing serialized packet
     /// 
-    /// @ret Result<(), ErrCode> - Returns the error enum derserialization fails
-    pub fn deserialize(&mut self, input: &[u8]) -> Result<(), ErrCode> {
+    /// @ret Result<(), DecodeError> - Returns the reason if derserialization fails
+    pub fn deserialize(&mut self, input: &[u8]) -> Result<(), DecodeError> {
+        if input.len() < Header::size_of() { return Err(DecodeError::Truncated); }
+
         let len = input[0];
         let packet_type = input[3];
         let packet_id = byteorder::NetworkEndian::read_u16(&input[1..3]);
         let crc = byteorder::NetworkEndian::read_u16(&input[4..6]);
         
         match packet_type {
-            0x00 => return Err(ErrCode::BROKEN),
             0x01 => self.packet_type = PacketType::Ok,
             0x02 => self.packet_type = PacketType::Err,
             0x03 => self.packet_type = PacketType::Mov,
@@ -67,7 +68,12 @@ impl Header {
             0x05 => self.packet_type = PacketType::Abord,
             0x06 => self.packet_type = PacketType::Prog,
             0x07 => self.packet_type = PacketType::Fin,
-            _ => return Err(ErrCode::BROKEN),
+            0x08 => self.packet_type = PacketType::Start,
+            0x09 => self.packet_type = PacketType::Hello,
+            0x0a => self.packet_type = PacketType::Info,
+            0x0b => self.packet_type = PacketType::Resume,
+            0x0c => self.packet_type = PacketType::MesBatch,
+            _ => return Err(DecodeError::UnknownType(packet_type)),
         }
 
         self.len = len;
@@ -79,11 +85,16 @@ impl Header {
     }
 
     /// Destructive method!!!
-    pub fn validate_crc(&self, buf: &mut [u8]) -> bool {
+    /// 
+    /// @ret Result<(), DecodeError> - CrcMismatch with the received and calculated Crc on failure
+    pub fn validate_crc(&self, buf: &mut [u8]) -> Result<(), DecodeError> {
         buf[4] = 0;
         buf[5] = 0;
         
-        self.crc == CRC_CALC.checksum(&buf[..self.len as usize])
+        let crc = CRC_CALC.checksum(&buf[..self.len as usize]);
+        if self.crc != crc { return Err(DecodeError::CrcMismatch { expected: self.crc, got: crc }); }
+
+        Ok(())
     }
 
     #[cfg(test)]
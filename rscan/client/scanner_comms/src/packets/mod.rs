@@ -1,16 +1,100 @@
+use core::ops::Deref;
+
+use byteorder::ByteOrder;
 use crc::Table;
 
+use header::Header;
+
 mod header;
 pub mod packet_ok;
 pub mod packet_err;
 pub mod packet_mov;
 pub mod packet_mes;
+pub mod packet_mes_batch;
 pub mod packet_abort;
 pub mod packet_prog;
 pub mod packet_fin;
+pub mod packet_start;
+pub mod packet_hello;
+pub mod packet_info;
+pub mod packet_resume;
+mod any_packet;
+
+pub use any_packet::AnyPacket;
+
+/// Revision of the protocol implemented by this crate, carried in every OK packet.
+///
+/// 1 - OK carries two sentinel bytes with the scan start position
+/// 2 - OK carries the acknowledged message ID, the scan start position is sent in START, HELLO/INFO handshake
+/// 3 - 16-bit point and line counts in PROG and START, signed 32-bit steps in MOV, 32-bit point count in FIN
+/// 4 - per-axis step size and microstepping mode in PROG
+/// 5 - MES_BATCH carrying consecutive measurements, acknowledged in a sliding window
+/// 6 - line and point index, signal strength and status in MES
+/// 7 - signal strength and status of every MES_BATCH measurement
+pub const PROTOCOL_VERSION: u8 = 7;
+
+/// Most measurements a single MES_BATCH carries.
+pub const MES_BATCH_MAX_POINTS: usize = 32;
+
+/// Most MES_BATCH packets the device may send before the oldest one is acknowledged.
+///
+/// Receivers have to remember at least as many message IDs to discard the duplicates.
+pub const MES_BATCH_WINDOW: usize = 4;
+
+/// Capability bits of the INFO packet.
+///
+/// CAP_ABORT - the device can abort a running scan
+/// CAP_MICROSTEPPING - the device honours the microstepping mode of PROG
+/// CAP_RESUME - the device can continue an interrupted scan with RESUME
+pub const CAP_ABORT: u16 = 0x0001;
+pub const CAP_MICROSTEPPING: u16 = 0x0002;
+pub const CAP_RESUME: u16 = 0x0004;
 
 const CRC_CALC: crc::Crc<u16, Table<1>> = crc::Crc::<u16, Table<1>>::new(&crc::CRC_16_XMODEM);
 
+/// Size of the biggest packet (not counting framing).
+pub const MAX_PACKET_SIZE: usize = max_size(&[
+    packet_ok::OkPacket::size_of(),
+    packet_err::ErrPacket::size_of(),
+    packet_mov::MovPacket::size_of(),
+    packet_mes::MesPacket::size_of(),
+    packet_mes_batch::MesBatchPacket::size_of(),
+    packet_abort::AbortPacket::size_of(),
+    packet_prog::ProgPacket::size_of(),
+    packet_fin::FinPacket::size_of(),
+    packet_start::StartPacket::size_of(),
+    packet_hello::HelloPacket::size_of(),
+    packet_info::InfoPacket::size_of(),
+    packet_resume::ResumePacket::size_of(),
+]);
+
+/// Size of the buffer able to hold any COBS framed packet, including the end delimiter.
+pub const MAX_FRAME_SIZE: usize = corncobs::max_encoded_len(MAX_PACKET_SIZE);
+
+/// Stack allocated COBS frame able to hold any packet.
+pub struct Frame {
+    buf: [u8; MAX_FRAME_SIZE],
+    len: usize,
+}
+
+impl Deref for Frame {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+const fn max_size(sizes: &[usize]) -> usize {
+    let mut max = 0;
+    let mut i = 0;
+    while i < sizes.len() {
+        if sizes[i] > max { max = sizes[i]; }
+        i += 1;
+    }
+    max
+}
+
 /// Enum type encoding packet types.
 /// 
 /// OK - acknowledgement that the command/mewasure has been received correctly
@@ -20,6 +104,11 @@ const CRC_CALC: crc::Crc<u16, Table<1>> = crc::Crc::<u16, Table<1>>::new(&crc::C
 /// ABORT - abort the current scan
 /// PROG - contains scan parameters
 /// FIN - scan has been finished
+/// START - position the accepted scan starts from, sent by the device after acknowledging PROG
+/// HELLO - handshake request sent by the client on connect
+/// INFO - protocol, firmware and motor description of the device, sent after acknowledging HELLO
+/// RESUME - scan parameters and the position an interrupted scan continues from, answered like PROG
+/// MES_BATCH - consecutive measurement data starting at the given index
 /// UNKNOWN - packet type is not known, something gone wrong. DO NOT SEND THIS VALUE!!!
 /// 
 #[repr(C)]
@@ -34,6 +123,11 @@ pub enum PacketType {
     Abord   = 0x05,
     Prog    = 0x06,
     Fin     = 0x07,
+    Start   = 0x08,
+    Hello   = 0x09,
+    Info    = 0x0a,
+    Resume  = 0x0b,
+    MesBatch = 0x0c,
     Uknown = 0xff,
 }
 
@@ -70,9 +164,181 @@ pub enum ErrCode {
     BROKEN,
 }
 
+/// Signal status of a MES measurement.
+///
+/// Valid - the distance can be trusted
+/// Weak - the return signal is weak, the distance may be off
+/// OutOfRange - nothing within the range of the lidar, the distance is meaningless
+/// Error - the lidar failed to measure the point
+///
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MesStatus {
+    Valid = 0x00,
+    Weak = 0x01,
+    OutOfRange = 0x02,
+    Error = 0x03,
+}
+
+impl MesStatus {
+    /// Checks if the distance of the measurement means anything
+    pub fn has_distance(&self) -> bool {
+        matches!(self, MesStatus::Valid | MesStatus::Weak)
+    }
+}
+
+/// Error type of the safe serialization API.
+///
+/// BufferTooSmall - the output slice cannot hold the framed packet.
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EncodeError {
+    BufferTooSmall,
+}
+
+/// Error type of the safe deserialization API.
+///
+/// Truncated - the frame ends before the packet does.
+/// LengthMismatch - the frame or the header length does not match the packet type, lengths are in bytes.
+/// Cobs - COBS framing is corrupted.
+/// UnknownType - the type byte is not known or not expected by the decoder.
+/// CrcMismatch - Crc validation failed, expected is the received Crc and got is the calculated one.
+/// InvalidField - one of the payload fields holds an invalid value.
+/// UnsupportedVersion - the packet comes from a peer speaking another protocol version.
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DecodeError {
+    Truncated,
+    LengthMismatch { expected: usize, got: usize },
+    Cobs,
+    UnknownType(u8),
+    CrcMismatch { expected: u16, got: u16 },
+    InvalidField,
+    UnsupportedVersion(u8),
+}
+
+impl DecodeError {
+    /// C compatible status code of the error
+    pub fn status(&self) -> DecodeStatus {
+        match self {
+            DecodeError::Truncated => DecodeStatus::Truncated,
+            DecodeError::LengthMismatch { .. } => DecodeStatus::LengthMismatch,
+            DecodeError::Cobs => DecodeStatus::Cobs,
+            DecodeError::UnknownType(_) => DecodeStatus::UnknownType,
+            DecodeError::CrcMismatch { .. } => DecodeStatus::CrcMismatch,
+            DecodeError::InvalidField => DecodeStatus::InvalidField,
+            DecodeError::UnsupportedVersion(_) => DecodeStatus::UnsupportedVersion,
+        }
+    }
+}
+
+impl core::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "frame truncated"),
+            DecodeError::LengthMismatch { expected, got } => write!(f, "length mismatch, expected {} bytes, got {}", expected, got),
+            DecodeError::Cobs => write!(f, "COBS framing corrupted"),
+            DecodeError::UnknownType(packet_type) => write!(f, "unknown packet type {:#04x}", packet_type),
+            DecodeError::CrcMismatch { expected, got } => write!(f, "Crc mismatch, expected {:#06x}, got {:#06x}", expected, got),
+            DecodeError::InvalidField => write!(f, "invalid payload field"),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported protocol version {}, expected {}", version, PROTOCOL_VERSION),
+        }
+    }
+}
+
+/// C compatible status code returned by the `*_try_deserialize` functions.
+///
+/// OK - packet has been deserialized
+/// Other values mirror the DecodeError variants.
+///
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DecodeStatus {
+    Ok = 0,
+    Truncated = 1,
+    LengthMismatch = 2,
+    Cobs = 3,
+    UnknownType = 4,
+    CrcMismatch = 5,
+    InvalidField = 6,
+    UnsupportedVersion = 7,
+}
+
 pub trait Packet {
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize;
 
     extern "C" fn deserialize(input: *mut u8, in_length: usize, out: &mut Self) -> usize;
+
+    extern "C" fn try_deserialize(input: *const u8, in_length: usize, out: &mut Self) -> DecodeStatus;
+
+    /// Serializes the packet and adds COBS framing.
+    /// out - a target slice, at least `size_of() + 2` long
+    ///
+    /// @ret Result<usize, EncodeError> - length that has been written
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError>;
+
+    /// Deserializes the packet from a COBS frame including the end delimiter.
+    /// input - slice containing the framed packet
+    ///
+    /// @ret Result<Self, DecodeError> - the packet or the reason it has been rejected
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> where Self: Sized;
+
+    /// Serializes the packet into a stack allocated frame, ready to be sent.
+    fn encode(&self) -> Frame {
+        let mut frame = Frame { buf: [0; MAX_FRAME_SIZE], len: 0 };
+        // MAX_FRAME_SIZE fits every packet, encoding cannot fail
+        frame.len = self.encode_into(&mut frame.buf).unwrap_or(0);
+        frame
+    }
+}
+
+/// Calculates Crc of the serialized packet, stores it in the header and adds COBS framing.
+///
+/// tmp_buf - serialized packet
+/// out - a target slice
+///
+/// @ret Result<usize, EncodeError> - length that has been written
+pub(crate) fn seal(tmp_buf: &mut [u8], out: &mut [u8]) -> Result<usize, EncodeError> {
+    if out.len() < corncobs::max_encoded_len(tmp_buf.len()) { return Err(EncodeError::BufferTooSmall); }
+
+    // Crc is calculated with the Crc field zeroed
+    tmp_buf[4] = 0;
+    tmp_buf[5] = 0;
+    let crc = CRC_CALC.checksum(tmp_buf);
+    byteorder::NetworkEndian::write_u16(&mut tmp_buf[4..6], crc);
+
+    Ok(corncobs::encode_buf(tmp_buf, out))
+}
+
+/// Removes COBS framing, reads the header and validates the Crc.
+///
+/// input - COBS framed packet including the end delimiter
+/// tmp_buf - scratch buffer at least as long as the input, the unframed packet is placed there
+///
+/// @ret Result<(Header, &[u8]), DecodeError> - the header and the payload that follows it
+pub(crate) fn unframe<'a>(input: &[u8], tmp_buf: &'a mut [u8]) -> Result<(Header, &'a [u8]), DecodeError> {
+    if tmp_buf.len() < input.len() { return Err(DecodeError::LengthMismatch { expected: tmp_buf.len(), got: input.len() }); }
+
+    // Removing COBS framing
+    let len = corncobs::decode_buf(input, tmp_buf).map_err(|e| match e {
+        corncobs::CobsError::Truncated => DecodeError::Truncated,
+        corncobs::CobsError::Corrupt => DecodeError::Cobs,
+    })?;
+
+    // Read header struct
+    let mut header = Header::new(0, 0, PacketType::Uknown);
+    header.deserialize(&tmp_buf[..len])?;
+    if (header.len as usize) > len { return Err(DecodeError::Truncated); }
+    if (header.len as usize) < len { return Err(DecodeError::LengthMismatch { expected: header.len as usize, got: len }); }
+
+    // Validate Crc
+    header.validate_crc(tmp_buf)?;
+
+    // Test code required for assertion
+    #[cfg(test)] {
+        header.zero_crc();
+    }
+
+    Ok((header, &tmp_buf[Header::size_of()..len]))
 }
 
@@ -1,19 +1,16 @@
 //use std::println;
 
-use byteorder::ByteOrder;
-
 use super::header::Header;
 use super::PacketType;
 use super::Packet;
-use super::CRC_CALC;
-
+use super::{DecodeError, DecodeStatus, EncodeError};
 
 
 
 #[repr(C)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct AbortPacket {
-    header: Header,
+    pub header: Header,
     sentinel: u8,
 }
 
@@ -30,71 +27,96 @@ impl AbortPacket {
         }
     }
 
-    const fn size_of() -> usize { Header::size_of() + 1 } // Remember to update max serialization size!!!
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Abord) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != AbortPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: AbortPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize sentinel
+        Ok(Self {
+            header,
+            sentinel: payload[0],
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 1 } // Remember to update max serialization size!!!
 }
 
 impl Packet for AbortPacket {
     #[no_mangle]
     #[export_name = "abort_packet_serialize"]
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
-        if out_length < AbortPacket::size_of() + 2 { return 0; }
+        if out.is_null() { return 0; }
 
         // Trapping a raw pointer into usable output slice
         let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
 
-        // Initializing temporary buffer where the packet gets constructed
-        let mut tmp_buf: [u8; AbortPacket::size_of()] = [0xff; AbortPacket::size_of()];
+        self.encode_into(out).unwrap_or(0)
+    }
 
-        // Serializing the initial header. Crc is zero!!!
-        let header_len = self.header.serialize(&mut tmp_buf);
-        tmp_buf[header_len] = self.sentinel;
+    #[no_mangle]
+    #[export_name = "abort_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut AbortPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
 
-        // Crc calculatrion step
-        let crc = CRC_CALC.checksum(&tmp_buf);
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Swapping initiali zero in Crc for an actual Crc value
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len - 2..header_len], crc);
-        
-        // Adding COBS framing and sending to the output provided by the caller
-        corncobs::encode_buf(&tmp_buf, out)
+        match AbortPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                AbortPacket::size_of()
+            }
+        }
     }
 
     #[no_mangle]
-    #[export_name = "abort_packet_deserialize"]
-    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut AbortPacket) -> usize {
-        // Checking if provided packet has a vaid size
-        if in_length != AbortPacket::size_of() + 2 {return 0;}
+    #[export_name = "abort_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut AbortPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
 
         // Trapping raw pointer in a useful slice
         let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Initialization of temporary buffer for deserizaliztion
-        let mut tmp_buf: [u8; AbortPacket::size_of() + 2] = [0; AbortPacket::size_of() + 2];
+        match AbortPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
 
-        // Removing COBS framing
-        let len = corncobs::decode_buf(input, &mut tmp_buf).unwrap_or(0);
-        // Return 0 if COBS removal failed
-        if len == 0 { return 0; }
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; AbortPacket::size_of()] = [0xff; AbortPacket::size_of()];
 
-        // Read header struct
-        match out.header.deserialize(&tmp_buf) {
-            Err(_) => return 0,
-            Ok(_) => {
-                // Validate Crc
-                if !out.header.validate_crc(&mut tmp_buf) { return 0; }
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
 
-                // Deserialize sentinel
-                out.sentinel = tmp_buf[6];
+        // Start of payload serialization
+        tmp_buf[header_len] = self.sentinel;
+        // End of payload serialization
 
-                // Test code required for assertion
-                #[cfg(test)] {
-                    out.header.zero_crc();
-                }
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
 
-                // Return packet length (not counting farming)
-                return 7;
-            }
-        }
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < AbortPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > AbortPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: AbortPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; AbortPacket::size_of() + 2] = [0; AbortPacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        AbortPacket::from_parts(header, payload)
     }
     
-}
\ No newline at end of file
+}
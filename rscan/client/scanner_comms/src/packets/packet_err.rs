@@ -1,11 +1,10 @@
 use byteorder::ByteOrder;
 
 use super::header::Header;
+use super::ErrCode;
 use super::PacketType;
 use super::Packet;
-use super::CRC_CALC;
-use super::ErrCode;
-
+use super::{DecodeError, DecodeStatus, EncodeError};
 
 
 
@@ -31,22 +30,84 @@ impl ErrPacket {
         }
     }
 
-    const fn size_of() -> usize { Header::size_of() + 3 } // Remember to update max serialization size!!!
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Err) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != ErrPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: ErrPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        let error = match payload[0] {
+            0x00 => ErrCode::UNKNOWN,
+            0x01 => ErrCode::BUSY,
+            0x02 => ErrCode::BROKEN,
+            _ => return Err(DecodeError::InvalidField),
+        };
+
+        Ok(Self {
+            header,
+            error,
+            packet_id: byteorder::NetworkEndian::read_u16(&payload[1..3]),
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 3 } // Remember to update max serialization size!!!
 }
 
 impl Packet for ErrPacket {
     #[no_mangle]
     #[export_name = "err_packet_serialize"]
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
-        if out_length < ErrPacket::size_of() + 2 { return 0; }
+        if out.is_null() { return 0; }
 
         // Trapping a raw pointer into usable output slice
         let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
 
+        self.encode_into(out).unwrap_or(0)
+    }
+
+    #[no_mangle]
+    #[export_name = "err_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut ErrPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match ErrPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                ErrPacket::size_of()
+            }
+        }
+    }
+
+    #[no_mangle]
+    #[export_name = "err_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut ErrPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match ErrPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
         // Initializing temporary buffer where the packet gets constructed
         let mut tmp_buf: [u8; ErrPacket::size_of()] = [0xff; ErrPacket::size_of()];
 
-        // Serializing the initial header. Crc is zero!!!
+        // Serializing the initial header
         let header_len = self.header.serialize(&mut tmp_buf);
 
         // Start of payload serialization
@@ -54,57 +115,20 @@ impl Packet for ErrPacket {
         byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+1..header_len+3], self.packet_id);
         // End of payload serialization
 
-        // Crc calculatrion step
-        let crc = CRC_CALC.checksum(&tmp_buf);
-
-        // Swapping initiali zero in Crc for an actual Crc value
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len - 2..header_len], crc);
-
-        // Adding COBS framing and sending to the output provided by the caller
-        corncobs::encode_buf(&tmp_buf, out)
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
     }
 
-    #[no_mangle]
-    #[export_name = "err_packet_deserialize"]
-    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut ErrPacket) -> usize {
-        if in_length != ErrPacket::size_of() + 2 {return 0;}
-
-        // Trapping raw pointer in a useful slice
-        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < ErrPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > ErrPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: ErrPacket::size_of() + 2, got: input.len() }); }
 
         // Initialization of temporary buffer for deserizaliztion
         let mut tmp_buf: [u8; ErrPacket::size_of() + 2] = [0; ErrPacket::size_of() + 2];
 
-        // Removing COBS framing
-        let len = corncobs::decode_buf(input, &mut tmp_buf).unwrap_or(0);
-        // Return 0 if COBS removal failed
-        if len == 0 { return 0; }
-
-        // Read header struct
-        match out.header.deserialize(&tmp_buf) {
-            Err(_) => return 0,
-            Ok(_) => {
-                // Validate Crc
-                if !out.header.validate_crc(&mut tmp_buf) { return 0; }
-
-                // Deserialize sentinel
-                match tmp_buf[6] {
-                    0x00 => out.error = ErrCode::UNKNOWN,
-                    0x01 => out.error = ErrCode::BUSY,
-                    0x02 => out.error = ErrCode::BROKEN,
-                    _ => return 0,
-                }
-                out.packet_id = byteorder::NetworkEndian::read_u16(& tmp_buf[7..9]);
-
-                // Test code required for assertion
-                #[cfg(test)] {
-                    out.header.zero_crc();
-                }
-
-                // Return packet length (not counting farming)
-                return ErrPacket::size_of();
-            }
-        }
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        ErrPacket::from_parts(header, payload)
     }
     
 }
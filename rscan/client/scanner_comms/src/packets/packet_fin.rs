@@ -3,22 +3,21 @@ use byteorder::ByteOrder;
 use super::header::Header;
 use super::PacketType;
 use super::Packet;
-use super::CRC_CALC;
-
+use super::{DecodeError, DecodeStatus, EncodeError};
 
 
 
 #[repr(C)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct FinPacket {
-    header: Header,
-    pub number_of_points: u16,
+    pub header: Header,
+    pub number_of_points: u32,
 }
 
 impl FinPacket {
     #[no_mangle]
     #[export_name = "fin_packet_new"]
-    pub extern "C" fn new(packet_id: u16, number_of_points: u16) -> Self {
+    pub extern "C" fn new(packet_id: u16, number_of_points: u32) -> Self {
         let size = FinPacket::size_of() as u8;
         let header = Header::new(size, packet_id, PacketType::Fin);
 
@@ -28,73 +27,96 @@ impl FinPacket {
         }
     }
 
-    const fn size_of() -> usize { Header::size_of() + 2 } // Remember to update max serialization size!!!
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Fin) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != FinPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: FinPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        Ok(Self {
+            header,
+            number_of_points: byteorder::NetworkEndian::read_u32(&payload[0..4]),
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 4 } // Remember to update max serialization size!!!
 }
 
 impl Packet for FinPacket {
     #[no_mangle]
     #[export_name = "fin_packet_serialize"]
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
-        if out_length < FinPacket::size_of() + 2 { return 0; }
+        if out.is_null() { return 0; }
 
         // Trapping a raw pointer into usable output slice
         let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
 
-        // Initializing temporary buffer where the packet gets constructed
-        let mut tmp_buf: [u8; FinPacket::size_of()] = [0xff; FinPacket::size_of()];
-
-        // Serializing the initial header. Crc is zero!!!
-        let header_len = self.header.serialize(&mut tmp_buf);
-
-        // Start of payload serialization
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len..header_len+2], self.number_of_points);
-        // End of payload serialization
+        self.encode_into(out).unwrap_or(0)
+    }
 
-        // Crc calculatrion step
-        let crc = CRC_CALC.checksum(&tmp_buf);
+    #[no_mangle]
+    #[export_name = "fin_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut FinPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
 
-        // Swapping initiali zero in Crc for an actual Crc value
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len - 2..header_len], crc);
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Adding COBS framing and sending to the output provided by the caller
-        corncobs::encode_buf(&tmp_buf, out)
+        match FinPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                FinPacket::size_of()
+            }
+        }
     }
 
     #[no_mangle]
-    #[export_name = "fin_packet_deserialize"]
-    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut FinPacket) -> usize {
-        if in_length != FinPacket::size_of() + 2 {return 0;}
+    #[export_name = "fin_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut FinPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
 
         // Trapping raw pointer in a useful slice
         let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Initialization of temporary buffer for deserizaliztion
-        let mut tmp_buf: [u8; FinPacket::size_of() + 2] = [0; FinPacket::size_of() + 2];
+        match FinPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
 
-        // Removing COBS framing
-        let len = corncobs::decode_buf(input, &mut tmp_buf).unwrap_or(0);
-        // Return 0 if COBS removal failed
-        if len == 0 { return 0; }
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; FinPacket::size_of()] = [0xff; FinPacket::size_of()];
 
-        // Read header struct
-        match out.header.deserialize(&tmp_buf) {
-            Err(_) => return 0,
-            Ok(_) => {
-                // Validate Crc
-                if !out.header.validate_crc(&mut tmp_buf) { return 0; }
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
 
-                // Deserialize sentinel
-                out.number_of_points = byteorder::NetworkEndian::read_u16(&tmp_buf[6..8]);
+        // Start of payload serialization
+        byteorder::NetworkEndian::write_u32(&mut tmp_buf[header_len..header_len+4], self.number_of_points);
+        // End of payload serialization
 
-                // Test code required for assertion
-                #[cfg(test)] {
-                    out.header.zero_crc();
-                }
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
 
-                // Return packet length (not counting farming)
-                return FinPacket::size_of();
-            }
-        }
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < FinPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > FinPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: FinPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; FinPacket::size_of() + 2] = [0; FinPacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        FinPacket::from_parts(header, payload)
     }
     
-}
\ No newline at end of file
+}
@@ -0,0 +1,124 @@
+use super::header::Header;
+use super::PacketType;
+use super::Packet;
+use super::{DecodeError, DecodeStatus, EncodeError};
+use super::PROTOCOL_VERSION;
+
+
+
+/// Handshake request sent by the client on connect, the device answers with INFO
+///
+/// version - protocol version of the client
+#[repr(C)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct HelloPacket {
+    pub header: Header,
+    pub version: u8,
+}
+
+impl HelloPacket {
+    #[no_mangle]
+    #[export_name = "hello_packet_new"]
+    pub extern "C" fn new(packet_id: u16) -> Self {
+        let size = HelloPacket::size_of() as u8;
+        let header = Header::new(size, packet_id, PacketType::Hello);
+
+        Self {
+            header,
+            version: PROTOCOL_VERSION,
+        }
+    }
+
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Hello) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != HelloPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: HelloPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        Ok(Self {
+            header,
+            version: payload[0],
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 1 } // Remember to update max serialization size!!!
+}
+
+impl Packet for HelloPacket {
+    #[no_mangle]
+    #[export_name = "hello_packet_serialize"]
+    extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
+        if out.is_null() { return 0; }
+
+        // Trapping a raw pointer into usable output slice
+        let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
+
+        self.encode_into(out).unwrap_or(0)
+    }
+
+    #[no_mangle]
+    #[export_name = "hello_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut HelloPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match HelloPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                HelloPacket::size_of()
+            }
+        }
+    }
+
+    #[no_mangle]
+    #[export_name = "hello_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut HelloPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match HelloPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; HelloPacket::size_of()] = [0xff; HelloPacket::size_of()];
+
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
+
+        // Start of payload serialization
+        tmp_buf[header_len] = self.version;
+        // End of payload serialization
+
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
+
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < HelloPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > HelloPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: HelloPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; HelloPacket::size_of() + 2] = [0; HelloPacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        HelloPacket::from_parts(header, payload)
+    }
+    
+}
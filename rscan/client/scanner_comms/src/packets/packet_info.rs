@@ -0,0 +1,164 @@
+use super::header::Header;
+use super::PacketType;
+use super::Packet;
+use super::{DecodeError, DecodeStatus, EncodeError};
+use super::PROTOCOL_VERSION;
+
+use byteorder::ByteOrder;
+
+
+
+/// Device description, answer to HELLO
+///
+/// protocol_version - protocol version of the firmware
+/// fw_major, fw_minor, fw_patch - firmware version
+/// horizon_step_angle - angle of a single horizon motor step in microdegrees
+/// azimuth_step_angle - angle of a single azimuth motor step in microdegrees
+/// max_steps_per_line - the highest number of points a line can have
+/// capabilities - CAP_* bits of the optional features supported by the device
+#[repr(C)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct InfoPacket {
+    pub header: Header,
+    pub protocol_version: u8,
+    pub fw_major: u8,
+    pub fw_minor: u8,
+    pub fw_patch: u8,
+    pub horizon_step_angle: u32,
+    pub azimuth_step_angle: u32,
+    pub max_steps_per_line: u16,
+    pub capabilities: u16,
+}
+
+impl InfoPacket {
+    #[no_mangle]
+    #[export_name = "info_packet_new"]
+    pub extern "C" fn new(packet_id: u16, fw_major: u8, fw_minor: u8, fw_patch: u8, horizon_step_angle: u32, azimuth_step_angle: u32, max_steps_per_line: u16, capabilities: u16) -> Self {
+        let size = InfoPacket::size_of() as u8;
+        let header = Header::new(size, packet_id, PacketType::Info);
+
+        Self {
+            header,
+            protocol_version: PROTOCOL_VERSION,
+            fw_major,
+            fw_minor,
+            fw_patch,
+            horizon_step_angle,
+            azimuth_step_angle,
+            max_steps_per_line,
+            capabilities,
+        }
+    }
+
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Info) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != InfoPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: InfoPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        Ok(Self {
+            header,
+            protocol_version: payload[0],
+            fw_major: payload[1],
+            fw_minor: payload[2],
+            fw_patch: payload[3],
+            horizon_step_angle: byteorder::NetworkEndian::read_u32(&payload[4..8]),
+            azimuth_step_angle: byteorder::NetworkEndian::read_u32(&payload[8..12]),
+            max_steps_per_line: byteorder::NetworkEndian::read_u16(&payload[12..14]),
+            capabilities: byteorder::NetworkEndian::read_u16(&payload[14..16]),
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    /// Checks if the device supports the feature
+    ///
+    /// capability - one of the CAP_* bits
+    pub fn supports(&self, capability: u16) -> bool { self.capabilities & capability != 0 }
+
+    pub const fn size_of() -> usize { Header::size_of() + 16 } // Remember to update max serialization size!!!
+}
+
+impl Packet for InfoPacket {
+    #[no_mangle]
+    #[export_name = "info_packet_serialize"]
+    extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
+        if out.is_null() { return 0; }
+
+        // Trapping a raw pointer into usable output slice
+        let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
+
+        self.encode_into(out).unwrap_or(0)
+    }
+
+    #[no_mangle]
+    #[export_name = "info_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut InfoPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match InfoPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                InfoPacket::size_of()
+            }
+        }
+    }
+
+    #[no_mangle]
+    #[export_name = "info_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut InfoPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match InfoPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; InfoPacket::size_of()] = [0xff; InfoPacket::size_of()];
+
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
+
+        // Start of payload serialization
+        tmp_buf[header_len] = self.protocol_version;
+        tmp_buf[header_len+1] = self.fw_major;
+        tmp_buf[header_len+2] = self.fw_minor;
+        tmp_buf[header_len+3] = self.fw_patch;
+        byteorder::NetworkEndian::write_u32(&mut tmp_buf[header_len+4..header_len+8], self.horizon_step_angle);
+        byteorder::NetworkEndian::write_u32(&mut tmp_buf[header_len+8..header_len+12], self.azimuth_step_angle);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+12..header_len+14], self.max_steps_per_line);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+14..header_len+16], self.capabilities);
+        // End of payload serialization
+
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
+
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < InfoPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > InfoPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: InfoPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; InfoPacket::size_of() + 2] = [0; InfoPacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        InfoPacket::from_parts(header, payload)
+    }
+    
+}
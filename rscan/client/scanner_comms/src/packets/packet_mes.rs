@@ -3,99 +3,152 @@ use byteorder::ByteOrder;
 use super::header::Header;
 use super::PacketType;
 use super::Packet;
-use super::CRC_CALC;
-
+use super::{DecodeError, DecodeStatus, EncodeError};
+use super::MesStatus;
 
 
 
+/// Single measurement placed in the scan grid
+///
+/// line - line of the measured point
+/// point - position of the point within the line
+/// mes - measured distance
+/// strength - signal strength reported by the lidar, 0 if not reported
+/// status - signal status of the measurement
 #[repr(C)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct MesPacket {
-    header: Header,
+    pub header: Header,
+    pub line: u16,
+    pub point: u16,
     pub mes: u32,
+    pub strength: u16,
+    pub status: MesStatus,
 }
 
 impl MesPacket {
     #[no_mangle]
     #[export_name = "mes_packet_new"]
-    pub extern "C" fn new(packet_id: u16, mes: u32) -> Self {
+    pub extern "C" fn new(packet_id: u16, line: u16, point: u16, mes: u32, strength: u16, status: MesStatus) -> Self {
         let size = MesPacket::size_of() as u8;
         let header = Header::new(size, packet_id, PacketType::Mes);
 
         Self {
             header,
+            line,
+            point,
             mes,
+            strength,
+            status,
         }
     }
 
-    const fn size_of() -> usize { Header::size_of() + 4 } // Remember to update max serialization size!!!
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Mes) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != MesPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: MesPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        let status = match payload[10] {
+            0x00 => MesStatus::Valid,
+            0x01 => MesStatus::Weak,
+            0x02 => MesStatus::OutOfRange,
+            0x03 => MesStatus::Error,
+            _ => return Err(DecodeError::InvalidField),
+        };
+
+        Ok(Self {
+            header,
+            line: byteorder::NetworkEndian::read_u16(&payload[0..2]),
+            point: byteorder::NetworkEndian::read_u16(&payload[2..4]),
+            mes: byteorder::NetworkEndian::read_u32(&payload[4..8]),
+            strength: byteorder::NetworkEndian::read_u16(&payload[8..10]),
+            status,
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 11 } // Remember to update max serialization size!!!
 }
 
 impl Packet for MesPacket {
     #[no_mangle]
     #[export_name = "mes_packet_serialize"]
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
-        if out_length < MesPacket::size_of() + 2 { return 0; }
+        if out.is_null() { return 0; }
 
         // Trapping a raw pointer into usable output slice
         let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
 
-        // Initializing temporary buffer where the packet gets constructed
-        let mut tmp_buf: [u8; MesPacket::size_of()] = [0xff; MesPacket::size_of()];
-
-        // Serializing the initial header. Crc is zero!!!
-        let header_len = self.header.serialize(&mut tmp_buf);
-
-        // Start of payload serialization
-        byteorder::NetworkEndian::write_u32(&mut tmp_buf[header_len..header_len+4], self.mes);
-        // End of payload serialization
+        self.encode_into(out).unwrap_or(0)
+    }
 
-        // Crc calculatrion step
-        let crc = CRC_CALC.checksum(&tmp_buf);
+    #[no_mangle]
+    #[export_name = "mes_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut MesPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
 
-        // Swapping initiali zero in Crc for an actual Crc value
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len - 2..header_len], crc);
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Adding COBS framing and sending to the output provided by the caller
-        corncobs::encode_buf(&tmp_buf, out)
+        match MesPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                MesPacket::size_of()
+            }
+        }
     }
 
     #[no_mangle]
-    #[export_name = "mes_packet_deserialize"]
-    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut MesPacket) -> usize {
-        // Checking if provided packet has a vaid size
-        if in_length != MesPacket::size_of() + 2 {return 0;}
+    #[export_name = "mes_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut MesPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
 
         // Trapping raw pointer in a useful slice
         let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Initialization of temporary buffer for deserizaliztion
-        let mut tmp_buf: [u8; MesPacket::size_of() + 2] = [0; MesPacket::size_of() + 2];
+        match MesPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; MesPacket::size_of()] = [0xff; MesPacket::size_of()];
+
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
 
-        // Removing COBS framing
-        let len = corncobs::decode_buf(input, &mut tmp_buf).unwrap_or(0);
-        // Return 0 if COBS removal failed
-        if len == 0 { return 0; }
+        // Start of payload serialization
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len..header_len+2], self.line);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+2..header_len+4], self.point);
+        byteorder::NetworkEndian::write_u32(&mut tmp_buf[header_len+4..header_len+8], self.mes);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+8..header_len+10], self.strength);
+        tmp_buf[header_len+10] = self.status as u8;
+        // End of payload serialization
 
-        // Read header struct
-        match out.header.deserialize(&tmp_buf) {
-            Err(_) => return 0,
-            Ok(_) => {
-                // Validate Crc
-                if !out.header.validate_crc(&mut tmp_buf) { return 0; }
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
 
-                // Deserialize sentinel
-                out.mes = byteorder::NetworkEndian::read_u32(&tmp_buf[6..10]);
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < MesPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > MesPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: MesPacket::size_of() + 2, got: input.len() }); }
 
-                // Test code required for assertion
-                #[cfg(test)] {
-                    out.header.zero_crc();
-                }
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; MesPacket::size_of() + 2] = [0; MesPacket::size_of() + 2];
 
-                // Return packet length (not counting farming)
-                return MesPacket::size_of();
-            }
-        }
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        MesPacket::from_parts(header, payload)
     }
     
-}
\ No newline at end of file
+}
@@ -0,0 +1,207 @@
+use byteorder::ByteOrder;
+
+use super::header::Header;
+use super::PacketType;
+use super::Packet;
+use super::{DecodeError, DecodeStatus, EncodeError};
+use super::{MesStatus, MES_BATCH_MAX_POINTS};
+
+/// Serialized size of a single measurement, distance, strength and status
+const POINT_SIZE: usize = 7;
+
+
+/// Consecutive measurements sent in a single packet
+///
+/// first_index - measurement order of the first distance in the scan
+/// count - number of measurements carried, at most MES_BATCH_MAX_POINTS
+/// distances - the distances, only the first count are valid
+/// strengths - signal strength of every distance, 0 if not reported
+/// statuses - signal status of every distance
+#[repr(C)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct MesBatchPacket {
+    pub header: Header,
+    pub first_index: u32,
+    pub count: u8,
+    pub distances: [u32; MES_BATCH_MAX_POINTS],
+    pub strengths: [u16; MES_BATCH_MAX_POINTS],
+    pub statuses: [MesStatus; MES_BATCH_MAX_POINTS],
+}
+
+impl MesBatchPacket {
+    /// Creates an empty batch, distances are added with push
+    #[no_mangle]
+    #[export_name = "mes_batch_packet_new"]
+    pub extern "C" fn new(packet_id: u16, first_index: u32) -> Self {
+        let size = MesBatchPacket::size_with(0) as u8;
+        let header = Header::new(size, packet_id, PacketType::MesBatch);
+
+        Self {
+            header,
+            first_index,
+            count: 0,
+            distances: [0; MES_BATCH_MAX_POINTS],
+            strengths: [0; MES_BATCH_MAX_POINTS],
+            statuses: [MesStatus::Valid; MES_BATCH_MAX_POINTS],
+        }
+    }
+
+    /// Appends a measurement to the batch
+    ///
+    /// distance - the measurement following the last one in the batch
+    /// strength - signal strength reported by the lidar, 0 if not reported
+    /// status - signal status of the measurement
+    ///
+    /// @ret bool - false if the batch is full
+    #[no_mangle]
+    #[export_name = "mes_batch_packet_push"]
+    pub extern "C" fn push(&mut self, distance: u32, strength: u16, status: MesStatus) -> bool {
+        if self.count as usize >= MES_BATCH_MAX_POINTS { return false; }
+
+        self.distances[self.count as usize] = distance;
+        self.strengths[self.count as usize] = strength;
+        self.statuses[self.count as usize] = status;
+        self.count += 1;
+        self.header.len = MesBatchPacket::size_with(self.count as usize) as u8;
+        true
+    }
+
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::MesBatch) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if payload.len() < 5 { return Err(DecodeError::Truncated); }
+
+        // Payload length is given by the count
+        let count = payload[4];
+        if count as usize > MES_BATCH_MAX_POINTS { return Err(DecodeError::InvalidField); }
+        if header.len as usize != MesBatchPacket::size_with(count as usize) { return Err(DecodeError::LengthMismatch { expected: MesBatchPacket::size_with(count as usize), got: header.len as usize }); }
+
+        // Deserialize payload
+        let mut distances = [0; MES_BATCH_MAX_POINTS];
+        let mut strengths = [0; MES_BATCH_MAX_POINTS];
+        let mut statuses = [MesStatus::Valid; MES_BATCH_MAX_POINTS];
+        for i in 0..count as usize {
+            let at = 5 + i * POINT_SIZE;
+            distances[i] = byteorder::NetworkEndian::read_u32(&payload[at..at+4]);
+            strengths[i] = byteorder::NetworkEndian::read_u16(&payload[at+4..at+6]);
+            statuses[i] = match payload[at+6] {
+                0x00 => MesStatus::Valid,
+                0x01 => MesStatus::Weak,
+                0x02 => MesStatus::OutOfRange,
+                0x03 => MesStatus::Error,
+                _ => return Err(DecodeError::InvalidField),
+            };
+        }
+
+        Ok(Self {
+            header,
+            first_index: byteorder::NetworkEndian::read_u32(&payload[0..4]),
+            count,
+            distances,
+            strengths,
+            statuses,
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    /// Distances carried by the packet
+    pub fn points(&self) -> &[u32] { &self.distances[..self.count as usize] }
+
+    /// Signal strengths of the carried distances
+    pub fn strengths(&self) -> &[u16] { &self.strengths[..self.count as usize] }
+
+    /// Signal statuses of the carried distances
+    pub fn statuses(&self) -> &[MesStatus] { &self.statuses[..self.count as usize] }
+
+    /// Size of the packet carrying count measurements
+    pub const fn size_with(count: usize) -> usize { Header::size_of() + 5 + count * POINT_SIZE }
+
+    /// Size of a full batch
+    pub const fn size_of() -> usize { MesBatchPacket::size_with(MES_BATCH_MAX_POINTS) } // Remember to update max serialization size!!!
+}
+
+impl Packet for MesBatchPacket {
+    #[no_mangle]
+    #[export_name = "mes_batch_packet_serialize"]
+    extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
+        if out.is_null() { return 0; }
+
+        // Trapping a raw pointer into usable output slice
+        let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
+
+        self.encode_into(out).unwrap_or(0)
+    }
+
+    #[no_mangle]
+    #[export_name = "mes_batch_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut MesBatchPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match MesBatchPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                out.header.len as usize
+            }
+        }
+    }
+
+    #[no_mangle]
+    #[export_name = "mes_batch_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut MesBatchPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match MesBatchPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; MesBatchPacket::size_of()] = [0xff; MesBatchPacket::size_of()];
+        let len = MesBatchPacket::size_with(self.count as usize);
+
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
+
+        // Start of payload serialization
+        byteorder::NetworkEndian::write_u32(&mut tmp_buf[header_len..header_len+4], self.first_index);
+        tmp_buf[header_len+4] = self.count;
+        for i in 0..self.count as usize {
+            let at = header_len + 5 + i * POINT_SIZE;
+            byteorder::NetworkEndian::write_u32(&mut tmp_buf[at..at+4], self.distances[i]);
+            byteorder::NetworkEndian::write_u16(&mut tmp_buf[at+4..at+6], self.strengths[i]);
+            tmp_buf[at+6] = self.statuses[i] as u8;
+        }
+        // End of payload serialization
+
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf[..len], out)
+    }
+
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size, the exact one is known from the header
+        if input.len() < MesBatchPacket::size_with(0) + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > MesBatchPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: MesBatchPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; MesBatchPacket::size_of() + 2] = [0; MesBatchPacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        MesBatchPacket::from_parts(header, payload)
+    }
+
+}
@@ -1,120 +1,143 @@
-use byteorder::ByteOrder;
-
 use super::header::Header;
 use super::Axis;
 use super::PacketType;
 use super::Packet;
 use super::RotSide;
-use super::CRC_CALC;
+use super::{DecodeError, DecodeStatus, EncodeError};
 
+use byteorder::ByteOrder;
 
 
 
+/// Move command
+///
+/// axis - axis of the rotation
+/// steps - step count, positive values rotate clockwise
 #[repr(C)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct MovPacket {
-    header: Header,
+    pub header: Header,
     pub axis: Axis,
-    pub side: RotSide,
-    pub steps: u8,
+    pub steps: i32,
 }
 
 impl MovPacket {
     #[no_mangle]
     #[export_name = "mov_packet_new"]
-    pub extern "C" fn new(packet_id: u16, axis: Axis, side: RotSide, steps: u8) -> Self {
+    pub extern "C" fn new(packet_id: u16, axis: Axis, steps: i32) -> Self {
         let size = MovPacket::size_of() as u8;
         let header = Header::new(size, packet_id, PacketType::Mov);
 
         Self {
             header,
             axis,
-            side,
             steps,
         }
     }
 
-    const fn size_of() -> usize { Header::size_of() + 3 } // Remember to update max serialization size!!!
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Mov) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != MovPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: MovPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        let axis = match payload[0] {
+            0x00 => Axis::Horizon,
+            0x01 => Axis::Azimuth,
+            _ => return Err(DecodeError::InvalidField),
+        };
+
+        Ok(Self {
+            header,
+            axis,
+            steps: byteorder::NetworkEndian::read_i32(&payload[1..5]),
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    /// Direction of the rotation derived from the step count sign
+    pub fn side(&self) -> RotSide {
+        if self.steps < 0 { RotSide::CounterClockwise } else { RotSide::Clockwise }
+    }
+
+    pub const fn size_of() -> usize { Header::size_of() + 5 } // Remember to update max serialization size!!!
 }
 
 impl Packet for MovPacket {
     #[no_mangle]
     #[export_name = "mov_packet_serialize"]
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
-        if out_length < MovPacket::size_of() + 2 { return 0; }
+        if out.is_null() { return 0; }
 
         // Trapping a raw pointer into usable output slice
         let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
 
+        self.encode_into(out).unwrap_or(0)
+    }
+
+    #[no_mangle]
+    #[export_name = "mov_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut MovPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match MovPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                MovPacket::size_of()
+            }
+        }
+    }
+
+    #[no_mangle]
+    #[export_name = "mov_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut MovPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match MovPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
         // Initializing temporary buffer where the packet gets constructed
         let mut tmp_buf: [u8; MovPacket::size_of()] = [0xff; MovPacket::size_of()];
 
-        // Serializing the initial header. Crc is zero!!!
+        // Serializing the initial header
         let header_len = self.header.serialize(&mut tmp_buf);
 
         // Start of payload serialization
         tmp_buf[header_len] = self.axis as u8;
-        tmp_buf[header_len+1] = self.side as u8;
-        tmp_buf[header_len+2] = self.steps;
+        byteorder::NetworkEndian::write_i32(&mut tmp_buf[header_len+1..header_len+5], self.steps);
         // End of payload serialization
 
-        // Crc calculatrion step
-        let crc = CRC_CALC.checksum(&tmp_buf);
-
-        // Swapping initiali zero in Crc for an actual Crc value
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len - 2..header_len], crc);
-
-        // Adding COBS framing and sending to the output provided by the caller
-        corncobs::encode_buf(&tmp_buf, out)
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
     }
 
-    #[no_mangle]
-    #[export_name = "mov_packet_deserialize"]
-    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut MovPacket) -> usize {
-        if in_length != MovPacket::size_of() + 2 {return 0;}
-
-        // Trapping raw pointer in a useful slice
-        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < MovPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > MovPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: MovPacket::size_of() + 2, got: input.len() }); }
 
         // Initialization of temporary buffer for deserizaliztion
         let mut tmp_buf: [u8; MovPacket::size_of() + 2] = [0; MovPacket::size_of() + 2];
 
-        // Removing COBS framing
-        let len = corncobs::decode_buf(input, &mut tmp_buf).unwrap_or(0);
-        // Return 0 if COBS removal failed
-        if len == 0 { return 0; }
-
-        // Read header struct
-        match out.header.deserialize(&tmp_buf) {
-            Err(_) => return 0,
-            Ok(_) => {
-                // Validate Crc
-                if !out.header.validate_crc(&mut tmp_buf) { return 0; }
-
-                // Deserialize sentinel
-                match tmp_buf[6] {
-                    0x00 => out.axis = Axis::Horizon,
-                    0x01 => out.axis = Axis::Azimuth,
-                    _ => return 0,
-                }
-                
-                match tmp_buf[7] {
-                    0x00 => out.side = RotSide::Clockwise,
-                    0x01 => out.side = RotSide::CounterClockwise,
-                    _ => return 0,
-                }
-                
-                out.steps = tmp_buf[8];
-
-                // Test code required for assertion
-                #[cfg(test)] {
-                    out.header.zero_crc();
-                }
-
-                // Return packet length (not counting farming)
-                return MovPacket::size_of();
-            }
-        }
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        MovPacket::from_parts(header, payload)
     }
     
-}
\ No newline at end of file
+}
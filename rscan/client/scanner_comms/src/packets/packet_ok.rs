@@ -1,9 +1,10 @@
-use byteorder::ByteOrder;
-
 use super::header::Header;
 use super::PacketType;
 use super::Packet;
-use super::CRC_CALC;
+use super::{DecodeError, DecodeStatus, EncodeError};
+use super::PROTOCOL_VERSION;
+
+use byteorder::ByteOrder;
 
 
 
@@ -12,96 +13,124 @@ use super::CRC_CALC;
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct OkPacket {
     pub header: Header,
-    pub sentinel: u8,
-    pub sentinel2: u8,
+    pub acked_id: u16,
+    pub version: u8,
 }
 
 impl OkPacket {
     #[no_mangle]
     #[export_name = "ok_packet_new"]
-    pub extern "C" fn new(packet_id: u16, sentinel: u8, sentinel2: u8) -> Self {
+    pub extern "C" fn new(packet_id: u16, acked_id: u16) -> Self {
         let size = OkPacket::size_of() as u8;
         let header = Header::new(size, packet_id, PacketType::Ok);
 
         Self {
             header,
-            sentinel,
-            sentinel2,
+            acked_id,
+            version: PROTOCOL_VERSION,
         }
     }
 
-    pub const fn size_of() -> usize { Header::size_of() + 2 } // Remember to update max serialization size!!!
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Ok) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        // Protocol version 1 OK carried two sentinel bytes instead of the acknowledged ID and version
+        if header.len as usize == OkPacket::LEGACY_SIZE { return Err(DecodeError::UnsupportedVersion(1)); }
+        if header.len as usize != OkPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: OkPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        let version = payload[2];
+        if version != PROTOCOL_VERSION { return Err(DecodeError::UnsupportedVersion(version)); }
+
+        Ok(Self {
+            header,
+            acked_id: byteorder::NetworkEndian::read_u16(&payload[0..2]),
+            version,
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 3 } // Remember to update max serialization size!!!
+
+    /// Size of the OK packet sent by protocol version 1 firmware
+    const LEGACY_SIZE: usize = Header::size_of() + 2;
 }
 
 impl Packet for OkPacket {
     #[no_mangle]
     #[export_name = "ok_packet_serialize"]
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
-
-        if out_length < OkPacket::size_of() + 2 { return 0; }
+        if out.is_null() { return 0; }
 
         // Trapping a raw pointer into usable output slice
         let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
 
-        // Initializing temporary buffer where the packet gets constructed
-        let mut tmp_buf: [u8; OkPacket::size_of()] = [0xff; OkPacket::size_of()];
-
-        // Serializing the initial header. Crc is zero!!!
-        let header_len = self.header.serialize(&mut tmp_buf);
-
-        // Start of payload serialization
-        tmp_buf[header_len] = self.sentinel;
-        tmp_buf[header_len+1] = self.sentinel2;
-        // End of payload serialization
+        self.encode_into(out).unwrap_or(0)
+    }
 
-        // Crc calculatrion step
-        let crc = CRC_CALC.checksum(&tmp_buf);
+    #[no_mangle]
+    #[export_name = "ok_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut OkPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
 
-        // Swapping initiali zero in Crc for an actual Crc value
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len - 2..header_len], crc);
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Adding COBS framing and sending to the output provided by the caller
-        corncobs::encode_buf(&tmp_buf, out)
+        match OkPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                OkPacket::size_of()
+            }
+        }
     }
 
     #[no_mangle]
-    #[export_name = "ok_packet_deserialize"]
-    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut OkPacket) -> usize {
-        // Checking if provided packet has a vaid size
-        if in_length != OkPacket::size_of() + 2 {return 0;}
+    #[export_name = "ok_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut OkPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
 
         // Trapping raw pointer in a useful slice
         let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Initialization of temporary buffer for deserizaliztion
-        let mut tmp_buf: [u8; OkPacket::size_of() + 2] = [0; OkPacket::size_of() + 2];
+        match OkPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
 
-        // Removing COBS framing
-        let len = corncobs::decode_buf(input, &mut tmp_buf).unwrap_or(0);
-        // Return 0 if COBS removal failed
-        if len == 0 { return 0; }
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; OkPacket::size_of()] = [0xff; OkPacket::size_of()];
 
-        // Read header struct
-        match out.header.deserialize(&tmp_buf) {
-            Err(_) => return 0,
-            Ok(_) => {
-                // Validate Crc
-                if !out.header.validate_crc(&mut tmp_buf) { return 0; }
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
 
-                // Deserialize sentinel
-                out.sentinel = tmp_buf[6];
-                out.sentinel2 = tmp_buf[7];
+        // Start of payload serialization
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len..header_len+2], self.acked_id);
+        tmp_buf[header_len+2] = self.version;
+        // End of payload serialization
 
-                // Test code required for assertion
-                #[cfg(test)] {
-                    out.header.zero_crc();
-                }
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
 
-                // Return packet length (not counting farming)
-                return 7;
-            }
-        }
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size, legacy frames are let through to report the version
+        if input.len() < OkPacket::LEGACY_SIZE + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > OkPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: OkPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; OkPacket::size_of() + 2] = [0; OkPacket::size_of() + 2];
 
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        OkPacket::from_parts(header, payload)
     }
     
 }
@@ -1,25 +1,34 @@
-use byteorder::ByteOrder;
-
 use super::header::Header;
 use super::PacketType;
 use super::Packet;
-use super::CRC_CALC;
+use super::{DecodeError, DecodeStatus, EncodeError};
 
+use byteorder::ByteOrder;
 
 
 
+/// Scan parameters
+///
+/// number_of_points - how many points a line has
+/// number_of_lines - how many lines the scan has
+/// point_step_size - steps made between two points of a line
+/// line_step_size - steps made between two lines
+/// microsteps - microsteps per full step the step sizes are counted in, 0 keeps the device default
 #[repr(C)]
 #[cfg_attr(test, derive(PartialEq, Debug))]
 pub struct ProgPacket {
     pub header: Header,
-    pub number_of_points: u8,
-    pub number_of_lines: u8,
+    pub number_of_points: u16,
+    pub number_of_lines: u16,
+    pub point_step_size: u16,
+    pub line_step_size: u16,
+    pub microsteps: u8,
 }
 
 impl ProgPacket {
     #[no_mangle]
     #[export_name = "prog_packet_new"]
-    pub extern "C" fn new(packet_id: u16, number_of_points: u8, number_of_lines: u8) -> Self {
+    pub extern "C" fn new(packet_id: u16, number_of_points: u16, number_of_lines: u16, point_step_size: u16, line_step_size: u16, microsteps: u8) -> Self {
         let size = ProgPacket::size_of() as u8;
         let header = Header::new(size, packet_id, PacketType::Prog);
 
@@ -27,77 +36,110 @@ impl ProgPacket {
             header,
             number_of_points,
             number_of_lines,
+            point_step_size,
+            line_step_size,
+            microsteps,
         }
     }
 
-    const fn size_of() -> usize { Header::size_of() + 2 } // Remember to update max serialization size!!!
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Prog) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != ProgPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: ProgPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        Ok(Self {
+            header,
+            number_of_points: byteorder::NetworkEndian::read_u16(&payload[0..2]),
+            number_of_lines: byteorder::NetworkEndian::read_u16(&payload[2..4]),
+            point_step_size: byteorder::NetworkEndian::read_u16(&payload[4..6]),
+            line_step_size: byteorder::NetworkEndian::read_u16(&payload[6..8]),
+            microsteps: payload[8],
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 9 } // Remember to update max serialization size!!!
 }
 
 impl Packet for ProgPacket {
     #[no_mangle]
     #[export_name = "prog_packet_serialize"]
     extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
-        if out_length < ProgPacket::size_of() + 2 { return 0; }
-        
+        if out.is_null() { return 0; }
+
         // Trapping a raw pointer into usable output slice
         let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
 
-        // Initializing temporary buffer where the packet gets constructed
-        let mut tmp_buf: [u8; ProgPacket::size_of()] = [0xff; ProgPacket::size_of()];
-
-        // Serializing the initial header. Crc is zero!!!
-        let header_len = self.header.serialize(&mut tmp_buf);
-        
-        tmp_buf[header_len] = self.number_of_points;
-        tmp_buf[header_len+1] = self.number_of_lines;
-        
-        let crc = CRC_CALC.checksum(&tmp_buf);
-
-        // Swapping initiali zero in Crc for an actual Crc value
-        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len - 2..header_len], crc);
-
-        // Adding COBS framing and sending to the output provided by the caller
-        corncobs::encode_buf(&tmp_buf, out)
-
-        // Start of payload serialization
+        self.encode_into(out).unwrap_or(0)
     }
 
     #[no_mangle]
     #[export_name = "prog_packet_deserialize"]
     extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut ProgPacket) -> usize {
-        if in_length != ProgPacket::size_of() + 2 {return 0;}
+        if input_ptr.is_null() { return 0; }
 
         // Trapping raw pointer in a useful slice
         let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
 
-        // Initialization of temporary buffer for deserizaliztion
-        let mut tmp_buf: [u8; ProgPacket::size_of() + 2] = [0; ProgPacket::size_of() + 2];
+        match ProgPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                ProgPacket::size_of()
+            }
+        }
+    }
 
-        // Removing COBS framing
-        let len = corncobs::decode_buf(input, &mut tmp_buf).unwrap_or(0);
-        // Return 0 if COBS removal failed
-        if len == 0 { return 0; }
-
-        // Read header struct
-        match out.header.deserialize(&tmp_buf) {
-            Err(_) => return 0,
-            Ok(_) => {
-                // Validate Crc
-                if !out.header.validate_crc(&mut tmp_buf) { return 0; }
-                
-                // Deserialize sentinel
-                out.number_of_points = tmp_buf[6];
-                out.number_of_lines = tmp_buf[7];
-
-                // Test code required for assertion
-                #[cfg(test)] {
-                    out.header.zero_crc();
-                }
+    #[no_mangle]
+    #[export_name = "prog_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut ProgPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
 
-                // Return packet length (not counting farming)
-                return 8;
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match ProgPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
             }
         }
     }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; ProgPacket::size_of()] = [0xff; ProgPacket::size_of()];
+
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
+
+        // Start of payload serialization
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len..header_len+2], self.number_of_points);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+2..header_len+4], self.number_of_lines);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+4..header_len+6], self.point_step_size);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+6..header_len+8], self.line_step_size);
+        tmp_buf[header_len+8] = self.microsteps;
+        // End of payload serialization
+
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
+
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < ProgPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > ProgPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: ProgPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; ProgPacket::size_of() + 2] = [0; ProgPacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        ProgPacket::from_parts(header, payload)
+    }
     
-}
\ No newline at end of file
+}
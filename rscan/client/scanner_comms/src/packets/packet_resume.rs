@@ -0,0 +1,151 @@
+use super::header::Header;
+use super::PacketType;
+use super::Packet;
+use super::{DecodeError, DecodeStatus, EncodeError};
+
+use byteorder::ByteOrder;
+
+
+
+/// Scan parameters of an interrupted scan and the position it continues from
+///
+/// number_of_points, number_of_lines, point_step_size, line_step_size, microsteps - the same as in PROG
+/// start_line - line of the first measurement still missing
+/// start_point - point of the first measurement still missing
+#[repr(C)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct ResumePacket {
+    pub header: Header,
+    pub number_of_points: u16,
+    pub number_of_lines: u16,
+    pub point_step_size: u16,
+    pub line_step_size: u16,
+    pub microsteps: u8,
+    pub start_line: u16,
+    pub start_point: u16,
+}
+
+impl ResumePacket {
+    #[no_mangle]
+    #[export_name = "resume_packet_new"]
+    pub extern "C" fn new(packet_id: u16, number_of_points: u16, number_of_lines: u16, point_step_size: u16, line_step_size: u16, microsteps: u8, start_line: u16, start_point: u16) -> Self {
+        let size = ResumePacket::size_of() as u8;
+        let header = Header::new(size, packet_id, PacketType::Resume);
+
+        Self {
+            header,
+            number_of_points,
+            number_of_lines,
+            point_step_size,
+            line_step_size,
+            microsteps,
+            start_line,
+            start_point,
+        }
+    }
+
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Resume) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != ResumePacket::size_of() { return Err(DecodeError::LengthMismatch { expected: ResumePacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        Ok(Self {
+            header,
+            number_of_points: byteorder::NetworkEndian::read_u16(&payload[0..2]),
+            number_of_lines: byteorder::NetworkEndian::read_u16(&payload[2..4]),
+            point_step_size: byteorder::NetworkEndian::read_u16(&payload[4..6]),
+            line_step_size: byteorder::NetworkEndian::read_u16(&payload[6..8]),
+            microsteps: payload[8],
+            start_line: byteorder::NetworkEndian::read_u16(&payload[9..11]),
+            start_point: byteorder::NetworkEndian::read_u16(&payload[11..13]),
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 13 } // Remember to update max serialization size!!!
+}
+
+impl Packet for ResumePacket {
+    #[no_mangle]
+    #[export_name = "resume_packet_serialize"]
+    extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
+        if out.is_null() { return 0; }
+
+        // Trapping a raw pointer into usable output slice
+        let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
+
+        self.encode_into(out).unwrap_or(0)
+    }
+
+    #[no_mangle]
+    #[export_name = "resume_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut ResumePacket) -> usize {
+        if input_ptr.is_null() { return 0; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match ResumePacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                ResumePacket::size_of()
+            }
+        }
+    }
+
+    #[no_mangle]
+    #[export_name = "resume_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut ResumePacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match ResumePacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; ResumePacket::size_of()] = [0xff; ResumePacket::size_of()];
+
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
+
+        // Start of payload serialization
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len..header_len+2], self.number_of_points);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+2..header_len+4], self.number_of_lines);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+4..header_len+6], self.point_step_size);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+6..header_len+8], self.line_step_size);
+        tmp_buf[header_len+8] = self.microsteps;
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+9..header_len+11], self.start_line);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+11..header_len+13], self.start_point);
+        // End of payload serialization
+
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
+
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < ResumePacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > ResumePacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: ResumePacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; ResumePacket::size_of() + 2] = [0; ResumePacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        ResumePacket::from_parts(header, payload)
+    }
+    
+}
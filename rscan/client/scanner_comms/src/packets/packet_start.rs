@@ -0,0 +1,130 @@
+use super::header::Header;
+use super::PacketType;
+use super::Packet;
+use super::{DecodeError, DecodeStatus, EncodeError};
+
+use byteorder::ByteOrder;
+
+
+
+/// Position the accepted scan starts from, answer to PROG
+///
+/// start_line - line the first measurement belongs to
+/// start_point - point of the line the first measurement belongs to
+#[repr(C)]
+#[cfg_attr(test, derive(PartialEq, Debug))]
+pub struct StartPacket {
+    pub header: Header,
+    pub start_line: u16,
+    pub start_point: u16,
+}
+
+impl StartPacket {
+    #[no_mangle]
+    #[export_name = "start_packet_new"]
+    pub extern "C" fn new(packet_id: u16, start_line: u16, start_point: u16) -> Self {
+        let size = StartPacket::size_of() as u8;
+        let header = Header::new(size, packet_id, PacketType::Start);
+
+        Self {
+            header,
+            start_line,
+            start_point,
+        }
+    }
+
+    /// Builds the packet from already unframed and validated header and payload
+    pub(crate) fn from_parts(header: Header, payload: &[u8]) -> Result<Self, DecodeError> {
+        if !matches!(header.packet_type, PacketType::Start) { return Err(DecodeError::UnknownType(header.packet_type as u8)); }
+        if header.len as usize != StartPacket::size_of() { return Err(DecodeError::LengthMismatch { expected: StartPacket::size_of(), got: header.len as usize }); }
+
+        // Deserialize payload
+        Ok(Self {
+            header,
+            start_line: byteorder::NetworkEndian::read_u16(&payload[0..2]),
+            start_point: byteorder::NetworkEndian::read_u16(&payload[2..4]),
+        })
+    }
+
+    /// Message ID of the packet
+    pub fn message_id(&self) -> u16 { self.header.packet_id }
+
+    pub const fn size_of() -> usize { Header::size_of() + 4 } // Remember to update max serialization size!!!
+}
+
+impl Packet for StartPacket {
+    #[no_mangle]
+    #[export_name = "start_packet_serialize"]
+    extern "C" fn serialize(&self, out: *mut u8, out_length: usize) -> usize {
+        if out.is_null() { return 0; }
+
+        // Trapping a raw pointer into usable output slice
+        let out = unsafe { core::slice::from_raw_parts_mut(out, out_length) };
+
+        self.encode_into(out).unwrap_or(0)
+    }
+
+    #[no_mangle]
+    #[export_name = "start_packet_deserialize"]
+    extern "C" fn deserialize(input_ptr: *mut u8, in_length: usize, out: &mut StartPacket) -> usize {
+        if input_ptr.is_null() { return 0; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match StartPacket::decode(input) {
+            Err(_) => 0,
+            Ok(pack) => {
+                *out = pack;
+                // Return packet length (not counting farming)
+                StartPacket::size_of()
+            }
+        }
+    }
+
+    #[no_mangle]
+    #[export_name = "start_packet_try_deserialize"]
+    extern "C" fn try_deserialize(input_ptr: *const u8, in_length: usize, out: &mut StartPacket) -> DecodeStatus {
+        if input_ptr.is_null() { return DecodeStatus::Truncated; }
+
+        // Trapping raw pointer in a useful slice
+        let input = unsafe { core::slice::from_raw_parts(input_ptr, in_length) };
+
+        match StartPacket::decode(input) {
+            Err(e) => e.status(),
+            Ok(pack) => {
+                *out = pack;
+                DecodeStatus::Ok
+            }
+        }
+    }
+
+    fn encode_into(&self, out: &mut [u8]) -> Result<usize, EncodeError> {
+        // Initializing temporary buffer where the packet gets constructed
+        let mut tmp_buf: [u8; StartPacket::size_of()] = [0xff; StartPacket::size_of()];
+
+        // Serializing the initial header
+        let header_len = self.header.serialize(&mut tmp_buf);
+
+        // Start of payload serialization
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len..header_len+2], self.start_line);
+        byteorder::NetworkEndian::write_u16(&mut tmp_buf[header_len+2..header_len+4], self.start_point);
+        // End of payload serialization
+
+        // Adding Crc and COBS framing
+        super::seal(&mut tmp_buf, out)
+    }
+
+    fn decode(input: &[u8]) -> Result<Self, DecodeError> {
+        // Checking if provided packet has a vaid size
+        if input.len() < StartPacket::size_of() + 2 { return Err(DecodeError::Truncated); }
+        if input.len() > StartPacket::size_of() + 2 { return Err(DecodeError::LengthMismatch { expected: StartPacket::size_of() + 2, got: input.len() }); }
+
+        // Initialization of temporary buffer for deserizaliztion
+        let mut tmp_buf: [u8; StartPacket::size_of() + 2] = [0; StartPacket::size_of() + 2];
+
+        let (header, payload) = super::unframe(input, &mut tmp_buf)?;
+        StartPacket::from_parts(header, payload)
+    }
+    
+}
@@ -0,0 +1,229 @@
+use crate::packets::MAX_FRAME_SIZE;
+
+/// Hands out message IDs using overflowing incrementation, as the protocol recommends.
+/// 
+/// Seed the allocator with something that differs between runs (i.e. time), so that IDs
+/// of a restarted peer are not taken for duplicates of the previous session.
+pub struct MessageIdAllocator {
+    next: u16,
+}
+
+impl MessageIdAllocator {
+    pub const fn new(seed: u16) -> Self {
+        MessageIdAllocator { next: seed }
+    }
+
+    /// Returns the next ID, wraps around after 0xffff
+    pub fn next_id(&mut self) -> u16 {
+        let id = self.next;
+        self.next = self.next.wrapping_add(1);
+        id
+    }
+}
+
+/// Retransmission parameters of the ReliableSender.
+/// 
+/// timeout_ms - time after which an unacknowledged packet gets retransmitted
+/// max_retries - number of retransmissions before the packet is considered lost
+#[derive(Clone, Copy, Debug)]
+pub struct TransportConfig {
+    pub timeout_ms: u32,
+    pub max_retries: u8,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        TransportConfig {
+            timeout_ms: 500,
+            max_retries: 5,
+        }
+    }
+}
+
+/// Errors returned when a packet cannot be tracked.
+/// 
+/// Full - all the slots are occupied by unacknowledged packets
+/// FrameTooLong - the frame does not fit into a slot
+/// IdInUse - packet with the same ID is still awaiting acknowledgement
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransportError {
+    Full,
+    FrameTooLong,
+    IdInUse,
+}
+
+/// Events reported by ReliableSender::poll.
+/// 
+/// Retransmit - packet has not been acknowledged in time, the frame must be sent again
+/// DeliveryFailed - retry budget has been exhausted, the packet is dropped
+#[derive(PartialEq, Debug)]
+pub enum TransportEvent<'a> {
+    Retransmit { id: u16, frame: &'a [u8] },
+    DeliveryFailed { id: u16 },
+}
+
+#[derive(Clone, Copy)]
+struct Outstanding {
+    id: u16,
+    frame: [u8; MAX_FRAME_SIZE],
+    len: usize,
+    sent_at: u32,
+    retries: u8,
+    due: bool,
+}
+
+/// Keeps copies of sent packets until they are acknowledged and schedules retransmissions.
+/// 
+/// The sender does not own the link nor the clock, the caller passes a monotonic
+/// millisecond timestamp and sends the frames returned by `poll` on its own.
+/// 
+/// N - number of packets that can await acknowledgement at once
+pub struct ReliableSender<const N: usize> {
+    slots: [Option<Outstanding>; N],
+    config: TransportConfig,
+}
+
+impl<const N: usize> ReliableSender<N> {
+    pub const fn new(config: TransportConfig) -> Self {
+        ReliableSender {
+            slots: [None; N],
+            config,
+        }
+    }
+
+    /// Registers a packet that has just been sent.
+    /// 
+    /// id - message ID of the packet
+    /// frame - COBS framed packet, kept for retransmission
+    /// now_ms - current time in milliseconds
+    pub fn track(&mut self, id: u16, frame: &[u8], now_ms: u32) -> Result<(), TransportError> {
+        if frame.len() > MAX_FRAME_SIZE { return Err(TransportError::FrameTooLong); }
+        if self.is_outstanding(id) { return Err(TransportError::IdInUse); }
+
+        let slot = self.slots.iter_mut().find(|slot| slot.is_none()).ok_or(TransportError::Full)?;
+
+        let mut outstanding = Outstanding {
+            id,
+            frame: [0; MAX_FRAME_SIZE],
+            len: frame.len(),
+            sent_at: now_ms,
+            retries: 0,
+            due: false,
+        };
+        outstanding.frame[..frame.len()].copy_from_slice(frame);
+        *slot = Some(outstanding);
+
+        Ok(())
+    }
+
+    /// Marks the packet as delivered.
+    /// 
+    /// @ret bool - false if no packet with such ID awaits acknowledgement
+    pub fn acknowledge(&mut self, id: u16) -> bool {
+        match self.slots.iter_mut().find(|slot| matches!(slot, Some(o) if o.id == id)) {
+            Some(slot) => {
+                *slot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Schedules immediate retransmission, i.e. after the receiver reported the packet broken.
+    /// 
+    /// @ret bool - false if no packet with such ID awaits acknowledgement
+    pub fn request_retransmit(&mut self, id: u16) -> bool {
+        match self.slots.iter_mut().flatten().find(|o| o.id == id) {
+            Some(outstanding) => {
+                outstanding.due = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks the timeouts, call periodically.
+    /// 
+    /// now_ms - current time in milliseconds
+    /// 
+    /// @ret Option<TransportEvent> - first event that is due, call again until None is returned
+    pub fn poll(&mut self, now_ms: u32) -> Option<TransportEvent<'_>> {
+        let config = self.config;
+        let index = self.slots.iter().position(|slot| match slot {
+            Some(o) => o.due || now_ms.wrapping_sub(o.sent_at) >= config.timeout_ms,
+            None => false,
+        })?;
+
+        if matches!(&self.slots[index], Some(o) if o.retries >= config.max_retries) {
+            let id = self.slots[index].take()?.id;
+            return Some(TransportEvent::DeliveryFailed { id });
+        }
+
+        let outstanding = self.slots[index].as_mut()?;
+        outstanding.retries += 1;
+        outstanding.sent_at = now_ms;
+        outstanding.due = false;
+        Some(TransportEvent::Retransmit { id: outstanding.id, frame: &outstanding.frame[..outstanding.len] })
+    }
+
+    /// Checks if the packet awaits acknowledgement
+    pub fn is_outstanding(&self, id: u16) -> bool {
+        self.slots.iter().flatten().any(|o| o.id == id)
+    }
+
+    /// Number of packets awaiting acknowledgement
+    pub fn pending(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+
+    /// Forgets all the packets awaiting acknowledgement
+    pub fn clear(&mut self) {
+        self.slots = [None; N];
+    }
+}
+
+/// Remembers IDs of the recently received packets to discard retransmitted duplicates.
+/// 
+/// Duplicates still have to be acknowledged, as the previous acknowledgement may have been lost.
+/// 
+/// N - number of remembered IDs
+pub struct DuplicateFilter<const N: usize> {
+    ids: [u16; N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> DuplicateFilter<N> {
+    pub const fn new() -> Self {
+        DuplicateFilter {
+            ids: [0; N],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    /// Registers received message ID.
+    /// 
+    /// @ret bool - true if the packet is new, false if it is a duplicate
+    pub fn check(&mut self, id: u16) -> bool {
+        if self.ids[..self.len].contains(&id) { return false; }
+
+        self.ids[self.next] = id;
+        self.next = (self.next + 1) % N;
+        if self.len < N { self.len += 1; }
+
+        true
+    }
+
+    /// Forgets all the remembered IDs
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.next = 0;
+    }
+}
+
+impl<const N: usize> Default for DuplicateFilter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
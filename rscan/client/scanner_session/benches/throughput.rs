@@ -0,0 +1,194 @@
+//! Scan throughput of single point MES against MES_BATCH over a simulated serial line.
+//!
+//! The session talks to a scripted device through a line adding the transfer time of
+//! every byte and a fixed latency to both directions. The tokio clock is paused, so the
+//! reported link time is simulated and the same on every run. Tokio timers round to
+//! milliseconds, the whole simulation therefore runs TIME_SCALE times slower.
+//!
+//! cargo bench -p scanner_session
+
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tokio_util::codec::FramedRead;
+
+use scanner_comms::codec::PacketCodec;
+use scanner_comms::packets::packet_fin::FinPacket;
+use scanner_comms::packets::packet_info::InfoPacket;
+use scanner_comms::packets::packet_mes::MesPacket;
+use scanner_comms::packets::packet_mes_batch::MesBatchPacket;
+use scanner_comms::packets::packet_ok::OkPacket;
+use scanner_comms::packets::packet_start::StartPacket;
+use scanner_comms::packets::{AnyPacket, MesStatus, Packet, CAP_ABORT, MES_BATCH_MAX_POINTS, MES_BATCH_WINDOW};
+use scanner_comms::transport::TransportConfig;
+use scanner_session::{ScanParams, ScannerSession, SessionConfig, SessionEvent};
+
+const POINTS: u16 = 60;
+const LINES: u16 = 50;
+
+/// 115200 baud, 8N1
+const BYTE_TIME: Duration = Duration::from_nanos(86_806);
+/// One way latency of the USB serial adapter
+const LATENCY: Duration = Duration::from_millis(1);
+/// Slowdown of the simulated time, keeps the timer rounding negligible
+const TIME_SCALE: u32 = 1000;
+
+/// How the device sends the measurements.
+///
+/// batch - points per packet, 1 sends MES
+/// window - packets sent before the oldest one has to be acknowledged
+#[derive(Clone, Copy)]
+struct Scheme {
+    name: &'static str,
+    batch: usize,
+    window: usize,
+}
+
+const SCHEMES: [Scheme; 4] = [
+    Scheme { name: "MES, stop and wait", batch: 1, window: 1 },
+    Scheme { name: "MES_BATCH 8, window 1", batch: 8, window: 1 },
+    Scheme { name: "MES_BATCH 8, window 4", batch: 8, window: MES_BATCH_WINDOW },
+    Scheme { name: "MES_BATCH 32, window 4", batch: MES_BATCH_MAX_POINTS, window: MES_BATCH_WINDOW },
+];
+
+fn main() {
+    println!("{} points, 115200 baud, {:?} latency", POINTS as u32 * LINES as u32, LATENCY);
+    println!("{:<26} {:>12} {:>12} {:>10} {:>12}", "scheme", "link time", "points/s", "frames", "wall time");
+
+    for scheme in SCHEMES {
+        let wall = std::time::Instant::now();
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().start_paused(true).build().unwrap();
+        let (link_time, frames) = runtime.block_on(scan(scheme));
+        let points = POINTS as f64 * LINES as f64;
+        println!("{:<26} {:>10.2} s {:>12.0} {:>10} {:>10.1?}", scheme.name, link_time.as_secs_f64(), points / link_time.as_secs_f64(), frames, wall.elapsed());
+    }
+}
+
+/// Runs a whole scan through the simulated line
+///
+/// @ret (Duration, u32) - simulated time from PROG to FIN and the number of measurement frames
+async fn scan(scheme: Scheme) -> (Duration, u32) {
+    let (client, client_line) = tokio::io::duplex(1 << 16);
+    let (device_io, device_line) = tokio::io::duplex(1 << 16);
+    let (client_rx, client_tx) = tokio::io::split(client_line);
+    let (device_rx, device_tx) = tokio::io::split(device_line);
+    tokio::spawn(line(client_rx, device_tx));
+    tokio::spawn(line(device_rx, client_tx));
+    let device = tokio::spawn(device(device_io, scheme));
+
+    let defaults = SessionConfig::default();
+    let config = SessionConfig {
+        transport: TransportConfig { timeout_ms: defaults.transport.timeout_ms * TIME_SCALE, ..defaults.transport },
+        idle_timeout: defaults.idle_timeout * TIME_SCALE,
+    };
+    let (session, mut events) = ScannerSession::spawn(client, config);
+    session.connect().await.unwrap();
+
+    let started = Instant::now();
+    session.start_scan(ScanParams::new(POINTS, LINES)).await.unwrap();
+    while let Some(event) = events.recv().await {
+        if let SessionEvent::ScanFinished { received, reported, missing } = event {
+            assert_eq!((received, reported, missing), (POINTS as u32 * LINES as u32, received, 0));
+            break;
+        }
+    }
+    (started.elapsed() / TIME_SCALE, device.await.unwrap())
+}
+
+/// One direction of the serial line, every byte arrives once transferred and after the latency
+async fn line(mut from: ReadHalf<DuplexStream>, mut to: WriteHalf<DuplexStream>) {
+    let (bytes_tx, mut bytes_rx) = mpsc::unbounded_channel::<(Instant, u8)>();
+    tokio::spawn(async move {
+        while let Some((arrival, byte)) = bytes_rx.recv().await {
+            tokio::time::sleep_until(arrival).await;
+            if to.write_all(&[byte]).await.is_err() { return; }
+        }
+    });
+
+    let mut free_at = Instant::now();
+    let mut buf = [0u8; 256];
+    loop {
+        let len = match from.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(len) => len,
+        };
+        // Bytes queue up behind the ones still being transferred
+        free_at = free_at.max(Instant::now());
+        for byte in &buf[..len] {
+            free_at += BYTE_TIME * TIME_SCALE;
+            if bytes_tx.send((free_at + LATENCY * TIME_SCALE, *byte)).is_err() { return; }
+        }
+    }
+}
+
+/// Device answering HELLO and PROG, then sending the scan as the scheme says
+///
+/// @ret u32 - number of measurement frames sent
+async fn device(io: DuplexStream, scheme: Scheme) -> u32 {
+    let (rx, mut port) = tokio::io::split(io);
+    let mut frames = FramedRead::new(rx, PacketCodec::new());
+    let mut next_id: u16 = 0;
+    let mut id = || { next_id = next_id.wrapping_add(1); next_id };
+
+    // Handshake and programming, every message of the device is acknowledged right away
+    loop {
+        let pack = frames.next().await.unwrap().unwrap().unwrap();
+        let reply = match pack {
+            AnyPacket::Hello(_) => InfoPacket::new(id(), 0, 1, 0, 1_800_000, 1_800_000, POINTS, CAP_ABORT).encode(),
+            AnyPacket::Prog(_) => StartPacket::new(id(), 0, 0).encode(),
+            _ => continue,
+        };
+        port.write_all(&OkPacket::new(id(), pack.message_id()).encode()).await.unwrap();
+        port.write_all(&reply).await.unwrap();
+        await_ok(&mut frames).await;
+        if matches!(pack, AnyPacket::Prog(_)) { break; }
+    }
+
+    let total = POINTS as u32 * LINES as u32;
+    let mut sent = 0;
+    let mut in_flight = 0;
+    let mut count = 0;
+    while sent < total {
+        let frame = match scheme.batch {
+            1 => {
+                // Serpentine from the first point of the first line
+                let (line, offset) = ((sent / POINTS as u32) as u16, (sent % POINTS as u32) as u16);
+                let point = if line % 2 == 0 { offset } else { POINTS - 1 - offset };
+                MesPacket::new(id(), line, point, sent + 1, 0, MesStatus::Valid).encode()
+            }
+            size => {
+                let mut batch = MesBatchPacket::new(id(), sent);
+                for distance in sent..total.min(sent + size as u32) {
+                    batch.push(distance + 1, 0, MesStatus::Valid);
+                }
+                batch.encode()
+            }
+        };
+        sent += scheme.batch.min((total - sent) as usize) as u32;
+        port.write_all(&frame).await.unwrap();
+        count += 1;
+        in_flight += 1;
+        while in_flight >= scheme.window {
+            await_ok(&mut frames).await;
+            in_flight -= 1;
+        }
+    }
+    while in_flight > 0 {
+        await_ok(&mut frames).await;
+        in_flight -= 1;
+    }
+
+    port.write_all(&FinPacket::new(id(), total).encode()).await.unwrap();
+    await_ok(&mut frames).await;
+    count
+}
+
+async fn await_ok(frames: &mut FramedRead<ReadHalf<DuplexStream>, PacketCodec>) {
+    match frames.next().await {
+        Some(Ok(Ok(AnyPacket::Ok(_)))) => (),
+        _ => panic!("expected ok"),
+    }
+}
@@ -0,0 +1,580 @@
+use futures::StreamExt;
+use log::{debug, info, warn};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{Instant, MissedTickBehavior};
+use tokio_util::codec::FramedRead;
+
+use scan_format::{ScanHeader, MISSING_DISTANCE};
+use scanner_comms::codec::PacketCodec;
+use scanner_comms::packets::packet_abort::AbortPacket;
+use scanner_comms::packets::packet_hello::HelloPacket;
+use scanner_comms::packets::packet_mes_batch::MesBatchPacket;
+use scanner_comms::packets::packet_mov::MovPacket;
+use scanner_comms::packets::packet_ok::OkPacket;
+use scanner_comms::packets::packet_prog::ProgPacket;
+use scanner_comms::packets::packet_resume::ResumePacket;
+use scanner_comms::packets::{AnyPacket, DecodeError, ErrCode, MesStatus, Packet, CAP_ABORT, CAP_MICROSTEPPING, CAP_RESUME, MES_BATCH_WINDOW, PROTOCOL_VERSION};
+use scanner_comms::transport::{DuplicateFilter, MessageIdAllocator, ReliableSender, TransportEvent};
+
+use crate::session::Command;
+use crate::{DeviceInfo, ScanParams, SessionConfig, SessionError, SessionEvent, SessionState};
+
+/// Command awaiting the response of the device.
+enum Pending {
+    Connect { id: u16, reply: oneshot::Sender<Result<DeviceInfo, SessionError>> },
+    Move { id: u16, reply: oneshot::Sender<Result<(), SessionError>> },
+    Prog { id: u16, reply: oneshot::Sender<Result<(), SessionError>> },
+    Abort { id: u16, reply: oneshot::Sender<Result<(), SessionError>> },
+}
+
+impl Pending {
+    fn id(&self) -> u16 {
+        match self {
+            Pending::Connect { id, .. } | Pending::Move { id, .. } | Pending::Prog { id, .. } | Pending::Abort { id, .. } => *id,
+        }
+    }
+
+    fn fail(self, e: SessionError) {
+        // The caller may have given up waiting, the reply is dropped then
+        let _ = match self {
+            Pending::Connect { reply, .. } => reply.send(Err(e)).is_ok(),
+            Pending::Move { reply, .. } | Pending::Prog { reply, .. } | Pending::Abort { reply, .. } => reply.send(Err(e)).is_ok(),
+        };
+    }
+}
+
+/// Scan in progress.
+///
+/// header - the scan, start position included once known
+/// received - index of the next expected measurement
+/// missing - number of points the device skipped
+/// resumed - the scan continues an interrupted one with RESUME
+struct Scan {
+    header: ScanHeader,
+    received: u32,
+    missing: u32,
+    resumed: bool,
+}
+
+/// Session task, owns the link and runs the state machine.
+pub(crate) struct Engine<T> {
+    frames: FramedRead<ReadHalf<T>, PacketCodec>,
+    port: WriteHalf<T>,
+    ids: MessageIdAllocator,
+    transport: ReliableSender<4>,
+    rx_filter: DuplicateFilter<{ 2 * MES_BATCH_WINDOW }>,
+    config: SessionConfig,
+    clock: Instant,
+    last_rx: Instant,
+    state: SessionState,
+    state_tx: watch::Sender<SessionState>,
+    events: mpsc::UnboundedSender<SessionEvent>,
+    device: Option<DeviceInfo>,
+    pending: Option<Pending>,
+    scan: Option<Scan>,
+}
+
+impl<T: AsyncRead + AsyncWrite> Engine<T> {
+    pub(crate) fn new(io: T, config: SessionConfig, state_tx: watch::Sender<SessionState>, events: mpsc::UnboundedSender<SessionEvent>) -> Self {
+        let (rx, tx) = tokio::io::split(io);
+        let clock = Instant::now();
+        Engine {
+            frames: FramedRead::new(rx, PacketCodec::new()),
+            port: tx,
+            ids: MessageIdAllocator::new(id_seed()),
+            transport: ReliableSender::new(config.transport),
+            rx_filter: DuplicateFilter::new(),
+            config,
+            clock,
+            last_rx: clock,
+            state: SessionState::Disconnected,
+            state_tx,
+            events,
+            device: None,
+            pending: None,
+            scan: None,
+        }
+    }
+
+    /// Runs until all the session handles are dropped or the link closes
+    pub(crate) async fn run(mut self, mut commands: mpsc::Receiver<Command>) {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_millis(50));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            let result = tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => self.command(command).await,
+                    None => break,
+                },
+                frame = self.frames.next() => match frame {
+                    Some(Ok(Ok(pack))) => self.packet(pack).await,
+                    Some(Ok(Err(DecodeError::UnsupportedVersion(version)))) => Err(SessionError::UnsupportedVersion(version)),
+                    Some(Ok(Err(e))) => {
+                        warn!("Frame dropped: {}", e);
+                        Ok(())
+                    }
+                    Some(Err(e)) => Err(e.into()),
+                    None => Err(SessionError::Closed),
+                },
+                _ = ticker.tick() => self.tick().await,
+            };
+
+            if let Err(e) = result {
+                let link_lost = matches!(e, SessionError::Closed | SessionError::Io(_));
+                self.fault(e);
+                if link_lost { break; }
+            }
+        }
+        debug!("Session task stopped");
+    }
+
+    async fn command(&mut self, command: Command) -> Result<(), SessionError> {
+        if self.pending.is_some() {
+            reject(command, SessionError::Busy);
+            return Ok(());
+        }
+
+        match command {
+            Command::Connect(reply) => {
+                if self.state.is_scanning() {
+                    let _ = reply.send(Err(SessionError::InvalidState(self.state)));
+                    return Ok(());
+                }
+                let hello = HelloPacket::new(self.ids.next_id());
+                self.pending = Some(Pending::Connect { id: hello.message_id(), reply });
+                self.send_tracked(hello.message_id(), &hello.encode()).await
+            }
+            Command::Move { axis, steps, reply } => {
+                if !matches!(self.state, SessionState::Idle | SessionState::Aborted) {
+                    let _ = reply.send(Err(SessionError::InvalidState(self.state)));
+                    return Ok(());
+                }
+                let mov = MovPacket::new(self.ids.next_id(), axis, steps);
+                self.pending = Some(Pending::Move { id: mov.message_id(), reply });
+                self.send_tracked(mov.message_id(), &mov.encode()).await
+            }
+            Command::StartScan { params, reply } => {
+                let device = match self.check_scan(&params) {
+                    Ok(device) => device,
+                    Err(e) => {
+                        let _ = reply.send(Err(e));
+                        return Ok(());
+                    }
+                };
+
+                self.scan = Some(Scan { header: scan_header(&params, &device), received: 0, missing: 0, resumed: false });
+                let prog = ProgPacket::new(self.ids.next_id(), params.number_of_points, params.number_of_lines, params.point_step_size, params.line_step_size, params.microsteps);
+                self.pending = Some(Pending::Prog { id: prog.message_id(), reply });
+                self.set_state(SessionState::Programming);
+                self.send_tracked(prog.message_id(), &prog.encode()).await
+            }
+            Command::Resume { header, received, reply } => {
+                let params = ScanParams::from(&header);
+                let checked = self.check_scan(&params).and_then(|device| match device.supports(CAP_RESUME) {
+                    false => Err(SessionError::Unsupported("resume")),
+                    true if received >= header.expected_points() => Err(SessionError::Complete),
+                    true => Ok(()),
+                });
+                if let Err(e) = checked {
+                    let _ = reply.send(Err(e));
+                    return Ok(());
+                }
+
+                let (line, point) = header.position(received);
+                info!("Resuming scan at line {:?} point {:?}", line, point);
+                let resume = ResumePacket::new(self.ids.next_id(), params.number_of_points, params.number_of_lines, params.point_step_size, params.line_step_size, params.microsteps, line, point);
+                self.scan = Some(Scan { header, received, missing: 0, resumed: true });
+                self.pending = Some(Pending::Prog { id: resume.message_id(), reply });
+                self.set_state(SessionState::Programming);
+                self.send_tracked(resume.message_id(), &resume.encode()).await
+            }
+            Command::Abort(reply) => {
+                if let Some(device) = self.device {
+                    if !device.supports(CAP_ABORT) {
+                        let _ = reply.send(Err(SessionError::Unsupported("abort")));
+                        return Ok(());
+                    }
+                }
+                let abort = AbortPacket::new(self.ids.next_id());
+                self.pending = Some(Pending::Abort { id: abort.message_id(), reply });
+                self.send_tracked(abort.message_id(), &abort.encode()).await
+            }
+        }
+    }
+
+    async fn packet(&mut self, pack: AnyPacket) -> Result<(), SessionError> {
+        self.last_rx = Instant::now();
+        match pack {
+            AnyPacket::Ok(pack) => {
+                if !self.transport.acknowledge(pack.acked_id) {
+                    debug!("Ok for packet {:?} that is not awaiting acknowledgement", pack.acked_id);
+                    return Ok(());
+                }
+                self.acknowledged(pack.acked_id);
+                Ok(())
+            }
+            AnyPacket::Err(pack) => {
+                if !self.transport.is_outstanding(pack.packet_id) {
+                    debug!("Error for packet {:?} that is not awaiting acknowledgement", pack.packet_id);
+                    return Ok(());
+                }
+                let why = match pack.error {
+                    ErrCode::BROKEN => {
+                        warn!("Packet {:?} reported broken, retransmitting", pack.packet_id);
+                        self.transport.request_retransmit(pack.packet_id);
+                        return Ok(());
+                    }
+                    ErrCode::BUSY => "device busy",
+                    ErrCode::UNKNOWN => "unknown error",
+                };
+                // Packet has been delivered, even though it has not been executed
+                self.transport.acknowledge(pack.packet_id);
+                if self.pending.as_ref().is_some_and(|pending| pending.id() == pack.packet_id) {
+                    if let Some(Pending::Prog { .. }) = self.pending {
+                        self.scan = None;
+                        self.set_state(SessionState::Idle);
+                    }
+                    if let Some(pending) = self.pending.take() { pending.fail(SessionError::Rejected(why)); }
+                }
+                Ok(())
+            }
+            AnyPacket::MesBatch(pack) => self.batch(pack).await,
+            pack => {
+                // Device messages are acknowledged even when duplicate, the previous ok got lost
+                let id = pack.message_id();
+                let ok = OkPacket::new(self.ids.next_id(), id);
+                self.port.write_all(&ok.encode()).await?;
+                if !self.rx_filter.check(id) {
+                    warn!("Duplicate packet {:?} ignored", id);
+                    return Ok(());
+                }
+
+                match pack {
+                    AnyPacket::Info(pack) => self.info(DeviceInfo::from(&pack)),
+                    AnyPacket::Start(pack) => self.start(pack.start_line, pack.start_point),
+                    AnyPacket::Mes(pack) => {
+                        match self.scan.as_ref().map(|scan| scan.header.index_of(pack.line, pack.point)) {
+                            Some(None) => warn!("Measurement at line {:?} point {:?} outside of the scan ignored", pack.line, pack.point),
+                            Some(Some(index)) => self.measurement(index, pack.mes, pack.strength, pack.status),
+                            None => warn!("Unexpected measurement in state {:?} ignored", self.state),
+                        }
+                        Ok(())
+                    }
+                    AnyPacket::Fin(pack) => {
+                        self.finish(pack.number_of_points);
+                        Ok(())
+                    }
+                    _ => {
+                        warn!("Unexpected packet {:?} from the device ignored", id);
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Completes the command the acknowledged packet belongs to
+    fn acknowledged(&mut self, id: u16) {
+        if self.pending.as_ref().map(Pending::id) != Some(id) { return; }
+        match self.pending.take() {
+            // Handshake completes with INFO
+            Some(pending @ Pending::Connect { .. }) => self.pending = Some(pending),
+            Some(Pending::Move { reply, .. }) => { let _ = reply.send(Ok(())); }
+            // Scan starts with START
+            Some(Pending::Prog { reply, .. }) => { let _ = reply.send(Ok(())); }
+            Some(Pending::Abort { reply, .. }) => {
+                if self.state.is_scanning() {
+                    let received = self.scan.take().map(|scan| scan.received).unwrap_or(0);
+                    info!("Scan aborted after {:?} points", received);
+                    self.emit(SessionEvent::ScanAborted { received });
+                    self.set_state(SessionState::Aborted);
+                }
+                let _ = reply.send(Ok(()));
+            }
+            None => (),
+        }
+    }
+
+    fn info(&mut self, device: DeviceInfo) -> Result<(), SessionError> {
+        if device.protocol_version != PROTOCOL_VERSION {
+            return Err(SessionError::UnsupportedVersion(device.protocol_version));
+        }
+        info!("Connected to firmware {:?}, capabilities {:#06x}", device.fw_version, device.capabilities);
+        self.device = Some(device);
+
+        if let Some(Pending::Connect { id, .. }) = self.pending {
+            // INFO follows the ok, HELLO has been received even if the ok got lost
+            self.transport.acknowledge(id);
+            if let Some(Pending::Connect { reply, .. }) = self.pending.take() {
+                let _ = reply.send(Ok(device));
+            }
+        }
+        if !self.state.is_scanning() {
+            self.set_state(SessionState::Idle);
+        }
+        self.emit(SessionEvent::Connected(device));
+        Ok(())
+    }
+
+    fn start(&mut self, start_line: u16, start_point: u16) -> Result<(), SessionError> {
+        if self.state != SessionState::Programming {
+            warn!("Unexpected start in state {:?} ignored", self.state);
+            return Ok(());
+        }
+        if let Some(Pending::Prog { id, .. }) = self.pending {
+            // START follows the ok, PROG has been received even if the ok got lost
+            self.transport.acknowledge(id);
+            self.acknowledged(id);
+        }
+        let scan = match self.scan.as_mut() {
+            Some(scan) => scan,
+            None => return Ok(()),
+        };
+
+        let event = if scan.resumed {
+            // Resumed scan keeps its original start, the device has to continue where asked
+            if scan.header.position(scan.received) != (start_line, start_point) {
+                return Err(SessionError::Rejected("resumed at other position"));
+            }
+            info!("Scan resumes at line {:?} point {:?}", start_line, start_point);
+            SessionEvent::ScanResumed { header: scan.header.clone(), received: scan.received }
+        } else {
+            scan.header.start_line = start_line;
+            scan.header.start_point = start_point;
+            info!("Scan starts at line {:?} point {:?}", start_line, start_point);
+            SessionEvent::ScanStarted(scan.header.clone())
+        };
+        self.set_state(SessionState::Measuring);
+        self.emit(event);
+        Ok(())
+    }
+
+    /// Places the measurement in the scan by its index
+    ///
+    /// Points skipped by the device are reported missing, repeated ones are rejected.
+    fn measurement(&mut self, index: u32, distance: u32, strength: u16, status: MesStatus) {
+        if self.state != SessionState::Measuring {
+            warn!("Unexpected measurement in state {:?} ignored", self.state);
+            return;
+        }
+        let scan = match self.scan.as_ref() {
+            Some(scan) => scan,
+            None => return,
+        };
+        if index >= scan.header.expected_points() {
+            warn!("Measurement past the end of the scan ignored");
+            return;
+        }
+        if index < scan.received {
+            warn!("Duplicate measurement of point {:?} rejected", index);
+            return;
+        }
+
+        self.skip_to(index);
+        let scan = match self.scan.as_mut() {
+            Some(scan) => scan,
+            None => return,
+        };
+        let (line, point) = scan.header.position(index);
+        scan.received = index + 1;
+        let complete = scan.received == scan.header.expected_points();
+
+        // The marker of missing points in the scan file cannot be a measured distance
+        let status = match status.has_distance() && distance == MISSING_DISTANCE {
+            true => {
+                warn!("Distance of point {:?} out of range", index);
+                MesStatus::OutOfRange
+            }
+            false => status,
+        };
+        self.emit(SessionEvent::Measurement { index, line, point, distance, strength, status });
+        if complete {
+            self.set_state(SessionState::Finishing);
+        }
+    }
+
+    /// Reports the points between the last received one and index missing
+    fn skip_to(&mut self, index: u32) {
+        let scan = match self.scan.as_mut() {
+            Some(scan) => scan,
+            None => return,
+        };
+        let index = index.min(scan.header.expected_points());
+        if index <= scan.received { return; }
+
+        warn!("Points {:?} to {:?} missing", scan.received, index - 1);
+        let gap: Vec<SessionEvent> = (scan.received..index)
+            .map(|index| {
+                let (line, point) = scan.header.position(index);
+                SessionEvent::Missing { index, line, point }
+            })
+            .collect();
+        scan.missing += index - scan.received;
+        scan.received = index;
+        for event in gap {
+            self.emit(event);
+        }
+    }
+
+    /// Stores the measurements of a batch, the device may send several before the first is acknowledged
+    ///
+    /// Batch following a lost one is left unacknowledged, so the device retransmits both in order.
+    async fn batch(&mut self, pack: MesBatchPacket) -> Result<(), SessionError> {
+        let id = pack.message_id();
+        let received = self.scan.as_ref().map_or(0, |scan| scan.received);
+        if self.state == SessionState::Measuring && pack.first_index > received {
+            debug!("Batch {:?} starts at point {:?}, awaiting {:?} first", id, pack.first_index, received);
+            return Ok(());
+        }
+
+        let ok = OkPacket::new(self.ids.next_id(), id);
+        self.port.write_all(&ok.encode()).await?;
+        if !self.rx_filter.check(id) {
+            warn!("Duplicate packet {:?} ignored", id);
+            return Ok(());
+        }
+
+        // Retransmitted batch may overlap the points that arrived meanwhile
+        let known = received.saturating_sub(pack.first_index) as usize;
+        let points = pack.points().iter().zip(pack.strengths()).zip(pack.statuses());
+        for (i, ((distance, strength), status)) in points.enumerate().skip(known) {
+            self.measurement(pack.first_index + i as u32, *distance, *strength, *status);
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, reported: u32) {
+        if !matches!(self.state, SessionState::Measuring | SessionState::Finishing) {
+            warn!("Unexpected fin in state {:?} ignored", self.state);
+            return;
+        }
+        // Points lost at the end of the scan are missing as well
+        self.skip_to(reported);
+        let (received, missing) = self.scan.take().map(|scan| (scan.received, scan.missing)).unwrap_or((0, 0));
+        if received != reported {
+            warn!("Device reported {:?} points, got {:?}", reported, received);
+        }
+        if missing > 0 {
+            warn!("Scan finished with {:?} points missing", missing);
+        }
+        self.emit(SessionEvent::ScanFinished { received, reported, missing });
+        self.set_state(SessionState::Idle);
+    }
+
+    /// Checks the scan can be programmed into the connected device
+    ///
+    /// @ret Result<DeviceInfo, SessionError> - the device the scan is meant for
+    fn check_scan(&self, params: &ScanParams) -> Result<DeviceInfo, SessionError> {
+        let device = match (self.state, self.device) {
+            (SessionState::Idle | SessionState::Aborted, Some(device)) => device,
+            (state, _) => return Err(SessionError::InvalidState(state)),
+        };
+        if params.number_of_points > device.max_steps_per_line {
+            return Err(SessionError::Unsupported("that many points per line"));
+        }
+        if params.microsteps > 0 && !device.supports(CAP_MICROSTEPPING) {
+            return Err(SessionError::Unsupported("microstepping"));
+        }
+        Ok(device)
+    }
+
+    /// Retransmits unacknowledged packets and watches the silence of the device
+    async fn tick(&mut self) -> Result<(), SessionError> {
+        let now = self.now_ms();
+        let mut retransmits = Vec::new();
+        let mut lost = None;
+        while let Some(event) = self.transport.poll(now) {
+            match event {
+                TransportEvent::Retransmit { id, frame } => {
+                    warn!("Packet {:?} not acknowledged, retransmitting", id);
+                    retransmits.push(frame.to_vec());
+                }
+                TransportEvent::DeliveryFailed { id } => lost = Some(id),
+            }
+        }
+        for frame in retransmits {
+            self.port.write_all(&frame).await?;
+        }
+        if let Some(id) = lost {
+            warn!("Packet {:?} lost, device does not respond!", id);
+            return Err(SessionError::NoResponse);
+        }
+
+        let waiting = self.pending.is_some() || self.state.is_scanning();
+        if waiting && self.last_rx.elapsed() > self.config.idle_timeout {
+            warn!("Device silent for {:?}", self.config.idle_timeout);
+            return Err(SessionError::NoResponse);
+        }
+        Ok(())
+    }
+
+    /// Gives up the running command and scan
+    fn fault(&mut self, e: SessionError) {
+        warn!("Session fault: {}", e);
+        if let Some(pending) = self.pending.take() {
+            pending.fail(e.clone());
+        }
+        self.scan = None;
+        self.transport.clear();
+        self.set_state(SessionState::Faulted);
+        self.emit(SessionEvent::Fault(e));
+    }
+
+    async fn send_tracked(&mut self, id: u16, frame: &[u8]) -> Result<(), SessionError> {
+        self.last_rx = Instant::now();
+        self.port.write_all(frame).await?;
+        let now = self.now_ms();
+        if let Err(e) = self.transport.track(id, frame, now) {
+            warn!("Packet {:?} will not be retransmitted: {:?}", id, e);
+        }
+        Ok(())
+    }
+
+    fn set_state(&mut self, state: SessionState) {
+        if self.state == state { return; }
+        debug!("Session state {:?} -> {:?}", self.state, state);
+        self.state = state;
+        self.state_tx.send_replace(state);
+        self.emit(SessionEvent::StateChanged(state));
+    }
+
+    fn emit(&self, event: SessionEvent) {
+        // Nobody listening is not an error
+        let _ = self.events.send(event);
+    }
+
+    /// Milliseconds since the session start, clock of the transport
+    fn now_ms(&self) -> u32 {
+        self.clock.elapsed().as_millis() as u32
+    }
+}
+
+/// Answers the command that cannot be executed
+fn reject(command: Command, e: SessionError) {
+    let _ = match command {
+        Command::Connect(reply) => reply.send(Err(e)).is_ok(),
+        Command::Move { reply, .. } | Command::StartScan { reply, .. } | Command::Resume { reply, .. } | Command::Abort(reply) => reply.send(Err(e)).is_ok(),
+    };
+}
+
+/// Header of the scan file described by the parameters and the device
+fn scan_header(params: &ScanParams, device: &DeviceInfo) -> ScanHeader {
+    let mut header = ScanHeader::new(params.number_of_points, params.number_of_lines);
+    header.point_step_size = params.point_step_size;
+    header.line_step_size = params.line_step_size;
+    header.microsteps = params.microsteps;
+    header.protocol_version = device.protocol_version;
+    header.fw_version = device.fw_version;
+    header.point_step_angle = device.horizon_step_angle;
+    header.line_step_angle = device.azimuth_step_angle;
+    header
+}
+
+/// Seed for message IDs, differs between the client runs
+fn id_seed() -> u16 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|t| t.subsec_nanos() as u16)
+        .unwrap_or(0)
+}
@@ -0,0 +1,618 @@
+//! UI independent client side of the scanner protocol.
+//!
+//! `ScannerSession` drives the device over any async byte stream. The protocol runs in
+//! a background task, the caller issues commands through the session handle and follows
+//! the progress through the stream of `SessionEvent`s.
+
+mod engine;
+mod session;
+
+use scan_format::ScanHeader;
+use scanner_comms::packets::packet_info::InfoPacket;
+use scanner_comms::packets::MesStatus;
+use scanner_comms::transport::TransportConfig;
+
+pub use session::ScannerSession;
+
+/// State of the session.
+///
+/// Disconnected - the device has not answered HELLO yet
+/// Idle - connected, ready for commands
+/// Programming - PROG has been sent, the device has not started the scan yet
+/// Measuring - the device sends the measurements
+/// Finishing - all the points have been received, awaiting FIN
+/// Aborted - the last scan has been aborted, ready for commands
+/// Faulted - the device stopped responding or speaks other protocol, connect again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    Disconnected,
+    Idle,
+    Programming,
+    Measuring,
+    Finishing,
+    Aborted,
+    Faulted,
+}
+
+impl SessionState {
+    /// Checks if a scan is in progress
+    pub fn is_scanning(&self) -> bool {
+        matches!(self, SessionState::Programming | SessionState::Measuring | SessionState::Finishing)
+    }
+}
+
+/// Errors of the session commands.
+///
+/// Closed - the link or the session task is gone
+/// NoResponse - the device did not acknowledge the packet or went silent
+/// Rejected - the device answered with ERR
+/// UnsupportedVersion - the device speaks other protocol version
+/// Unsupported - the device lacks the capability the command needs
+/// InvalidState - the command cannot be issued in the current state
+/// Busy - other command is still awaiting its response
+/// Complete - the scan to resume has all its points already
+/// Io - the link failed
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionError {
+    Closed,
+    NoResponse,
+    Rejected(&'static str),
+    UnsupportedVersion(u8),
+    Unsupported(&'static str),
+    InvalidState(SessionState),
+    Busy,
+    Complete,
+    Io(std::io::ErrorKind),
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Closed => write!(f, "link closed"),
+            SessionError::NoResponse => write!(f, "device does not respond"),
+            SessionError::Rejected(why) => write!(f, "device rejected the command: {}", why),
+            SessionError::UnsupportedVersion(version) => write!(f, "device speaks protocol version {}, client requires {}", version, scanner_comms::packets::PROTOCOL_VERSION),
+            SessionError::Unsupported(what) => write!(f, "device does not support {}", what),
+            SessionError::InvalidState(state) => write!(f, "command not allowed in state {:?}", state),
+            SessionError::Busy => write!(f, "previous command still in progress"),
+            SessionError::Complete => write!(f, "scan already complete"),
+            SessionError::Io(kind) => write!(f, "io error: {}", kind),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<std::io::Error> for SessionError {
+    fn from(e: std::io::Error) -> Self {
+        SessionError::Io(e.kind())
+    }
+}
+
+/// Description of the connected device, taken from INFO.
+///
+/// protocol_version - protocol version of the firmware
+/// fw_version - major, minor and patch version of the firmware
+/// horizon_step_angle, azimuth_step_angle - angle of a single motor step in microdegrees
+/// max_steps_per_line - the highest number of points a line can have
+/// capabilities - CAP_* bits of the optional features
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub protocol_version: u8,
+    pub fw_version: [u8; 3],
+    pub horizon_step_angle: u32,
+    pub azimuth_step_angle: u32,
+    pub max_steps_per_line: u16,
+    pub capabilities: u16,
+}
+
+impl DeviceInfo {
+    /// Checks if the device supports the CAP_* feature
+    pub fn supports(&self, cap: u16) -> bool {
+        self.capabilities & cap == cap
+    }
+}
+
+impl From<&InfoPacket> for DeviceInfo {
+    fn from(pack: &InfoPacket) -> Self {
+        DeviceInfo {
+            protocol_version: pack.protocol_version,
+            fw_version: [pack.fw_major, pack.fw_minor, pack.fw_patch],
+            horizon_step_angle: pack.horizon_step_angle,
+            azimuth_step_angle: pack.azimuth_step_angle,
+            max_steps_per_line: pack.max_steps_per_line,
+            capabilities: pack.capabilities,
+        }
+    }
+}
+
+/// Parameters of a scan, the fields of PROG.
+///
+/// number_of_points - points of a line
+/// number_of_lines - number of lines
+/// point_step_size, line_step_size - motor steps between two points and two lines
+/// microsteps - microsteps per full step the step sizes are counted in, 0 keeps the device default
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScanParams {
+    pub number_of_points: u16,
+    pub number_of_lines: u16,
+    pub point_step_size: u16,
+    pub line_step_size: u16,
+    pub microsteps: u8,
+}
+
+impl ScanParams {
+    /// Scan of the given size moving one step between the points, with the default microstepping
+    pub fn new(number_of_points: u16, number_of_lines: u16) -> Self {
+        ScanParams { number_of_points, number_of_lines, point_step_size: 1, line_step_size: 1, microsteps: 0 }
+    }
+
+    /// Number of points the scan consists of
+    pub fn expected_points(&self) -> u32 {
+        self.number_of_points as u32 * self.number_of_lines as u32
+    }
+}
+
+impl From<&ScanHeader> for ScanParams {
+    fn from(header: &ScanHeader) -> Self {
+        ScanParams {
+            number_of_points: header.number_of_points,
+            number_of_lines: header.number_of_lines,
+            point_step_size: header.point_step_size,
+            line_step_size: header.line_step_size,
+            microsteps: header.microsteps,
+        }
+    }
+}
+
+/// Progress reported by the session.
+///
+/// StateChanged - the session moved to the new state
+/// Connected - the device answered the handshake
+/// ScanStarted - the device started the scan, the header describes it including the start position
+/// ScanResumed - the device continues the interrupted scan, the next measurement has index received
+/// Measurement - a point has been received, index in the measurement order, its position in the grid and signal quality
+/// Missing - the device skipped the point, later points have been received already
+/// ScanFinished - FIN received, received and reported number of points, missing points counted in received
+/// ScanAborted - the scan has been aborted after the given number of points
+/// Fault - the session gave up on the device
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    StateChanged(SessionState),
+    Connected(DeviceInfo),
+    ScanStarted(ScanHeader),
+    ScanResumed { header: ScanHeader, received: u32 },
+    Measurement { index: u32, line: u16, point: u16, distance: u32, strength: u16, status: MesStatus },
+    Missing { index: u32, line: u16, point: u16 },
+    ScanFinished { received: u32, reported: u32, missing: u32 },
+    ScanAborted { received: u32 },
+    Fault(SessionError),
+}
+
+/// Settings of the session.
+///
+/// transport - retransmission timing of the sent packets
+/// idle_timeout - longest silence of the device while a response or a measurement is expected
+#[derive(Clone, Copy, Debug)]
+pub struct SessionConfig {
+    pub transport: TransportConfig,
+    pub idle_timeout: std::time::Duration,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            transport: TransportConfig::default(),
+            idle_timeout: std::time::Duration::from_secs(10),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use tokio::io::{AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+    use tokio::sync::mpsc::UnboundedReceiver;
+    use tokio_util::codec::FramedRead;
+
+    use scanner_comms::codec::PacketCodec;
+    use scanner_comms::packets::packet_err::ErrPacket;
+    use scanner_comms::packets::packet_fin::FinPacket;
+    use scanner_comms::packets::packet_mes::MesPacket;
+    use scanner_comms::packets::packet_mes_batch::MesBatchPacket;
+    use scanner_comms::packets::packet_ok::OkPacket;
+    use scanner_comms::packets::packet_start::StartPacket;
+    use scanner_comms::packets::{AnyPacket, Axis, ErrCode, Packet, CAP_ABORT, CAP_RESUME, PROTOCOL_VERSION};
+
+    use super::*;
+
+    /// Device end of the in-memory link.
+    struct FakeDevice {
+        frames: FramedRead<ReadHalf<DuplexStream>, PacketCodec>,
+        port: WriteHalf<DuplexStream>,
+        next_id: u16,
+    }
+
+    impl FakeDevice {
+        fn id(&mut self) -> u16 {
+            self.next_id += 1;
+            self.next_id
+        }
+
+        async fn recv(&mut self) -> AnyPacket {
+            self.frames.next().await.expect("link closed").expect("io error").expect("broken frame")
+        }
+
+        async fn send(&mut self, frame: &[u8]) {
+            self.port.write_all(frame).await.unwrap();
+        }
+
+        async fn ack(&mut self, acked_id: u16) {
+            let ok = OkPacket::new(self.id(), acked_id);
+            self.send(&ok.encode()).await;
+        }
+
+        /// Sends a message and checks the client acknowledges it
+        async fn deliver(&mut self, frame: &[u8], id: u16) {
+            self.send(frame).await;
+            match self.recv().await {
+                AnyPacket::Ok(ok) => assert_eq!(ok.acked_id, id),
+                _ => panic!("expected ok"),
+            }
+        }
+
+        async fn answer_hello(&mut self, info: InfoPacket) {
+            let AnyPacket::Hello(hello) = self.recv().await else { panic!("expected hello") };
+            self.ack(hello.message_id()).await;
+            self.deliver(&info.encode(), info.message_id()).await;
+        }
+
+        async fn mes(&mut self, line: u16, point: u16, distance: u32) {
+            let mes = MesPacket::new(self.id(), line, point, distance, 0, MesStatus::Valid);
+            self.deliver(&mes.encode(), mes.message_id()).await;
+        }
+    }
+
+    fn batch(id: u16, first_index: u32, distances: &[u32]) -> MesBatchPacket {
+        let mut batch = MesBatchPacket::new(id, first_index);
+        for distance in distances {
+            batch.push(*distance, 0, MesStatus::Valid);
+        }
+        batch
+    }
+
+    /// Positions and distances of the measurement events
+    fn measurements(events: &[SessionEvent]) -> Vec<(u16, u16, u32)> {
+        events.iter()
+            .filter_map(|event| match event {
+                SessionEvent::Measurement { line, point, distance, .. } => Some((*line, *point, *distance)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn setup() -> (ScannerSession, UnboundedReceiver<SessionEvent>, FakeDevice) {
+        let (client, device) = tokio::io::duplex(1024);
+        let (session, events) = ScannerSession::spawn(client, SessionConfig::default());
+        let (rx, tx) = tokio::io::split(device);
+        (session, events, FakeDevice { frames: FramedRead::new(rx, PacketCodec::new()), port: tx, next_id: 1000 })
+    }
+
+    fn info(id: u16) -> InfoPacket {
+        InfoPacket::new(id, 0, 4, 0, 1_800_000, 1_800_000, 400, CAP_ABORT | CAP_RESUME)
+    }
+
+    async fn connected() -> (ScannerSession, UnboundedReceiver<SessionEvent>, FakeDevice) {
+        let (session, events, mut device) = setup();
+        let info = info(device.id());
+        let (connect, _) = tokio::join!(session.connect(), device.answer_hello(info));
+        connect.unwrap();
+        (session, events, device)
+    }
+
+    /// Collects the events up to and including the first matching one
+    async fn until(events: &mut UnboundedReceiver<SessionEvent>, last: impl Fn(&SessionEvent) -> bool) -> Vec<SessionEvent> {
+        let mut seen = Vec::new();
+        while let Some(event) = events.recv().await {
+            let done = last(&event);
+            seen.push(event);
+            if done { return seen; }
+        }
+        panic!("event stream ended, got {:?}", seen);
+    }
+
+    async fn start(session: &ScannerSession, device: &mut FakeDevice, params: ScanParams) {
+        let program = async {
+            let AnyPacket::Prog(prog) = device.recv().await else { panic!("expected prog") };
+            assert_eq!(prog.number_of_points, params.number_of_points);
+            device.ack(prog.message_id()).await;
+            let start = StartPacket::new(device.id(), 0, 0);
+            device.deliver(&start.encode(), start.message_id()).await;
+        };
+        let (result, _) = tokio::join!(session.start_scan(params), program);
+        result.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn handshake_reports_device() {
+        let (session, mut events, mut device) = setup();
+        assert_eq!(session.move_axis(Axis::Horizon, 10).await, Err(SessionError::InvalidState(SessionState::Disconnected)));
+
+        let info = info(device.id());
+        let (connect, _) = tokio::join!(session.connect(), device.answer_hello(info));
+        let device_info = connect.unwrap();
+        assert_eq!(device_info.fw_version, [0, 4, 0]);
+        assert_eq!(device_info.max_steps_per_line, 400);
+        assert!(device_info.supports(CAP_ABORT));
+        assert_eq!(session.state(), SessionState::Idle);
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::Connected(_))).await;
+        assert!(seen.contains(&SessionEvent::StateChanged(SessionState::Idle)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn scan_follows_serpentine_order() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+        assert_eq!(session.state(), SessionState::Measuring);
+
+        for (line, point, distance) in [(0, 0, 100), (0, 1, 101), (0, 2, 102), (1, 2, 103), (1, 1, 104), (1, 0, 105)] {
+            device.mes(line, point, distance).await;
+        }
+        let fin = FinPacket::new(device.id(), 6);
+        device.deliver(&fin.encode(), fin.message_id()).await;
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::ScanFinished { .. })).await;
+        assert_eq!(measurements(&seen), vec![(0, 0, 100), (0, 1, 101), (0, 2, 102), (1, 2, 103), (1, 1, 104), (1, 0, 105)]);
+        assert!(seen.contains(&SessionEvent::StateChanged(SessionState::Finishing)));
+        assert_eq!(seen.last(), Some(&SessionEvent::ScanFinished { received: 6, reported: 6, missing: 0 }));
+        assert_eq!(session.state(), SessionState::Idle);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batches_acknowledged_in_window() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+
+        // Second batch leaves before the first one is acknowledged
+        let first = batch(device.id(), 0, &[100, 101, 102, 103]);
+        let second = batch(device.id(), 4, &[104, 105]);
+        device.send(&first.encode()).await;
+        device.send(&second.encode()).await;
+        for id in [first.message_id(), second.message_id()] {
+            let AnyPacket::Ok(ok) = device.recv().await else { panic!("expected ok") };
+            assert_eq!(ok.acked_id, id);
+        }
+        assert_eq!(session.state(), SessionState::Finishing);
+
+        // Retransmission of an acknowledged batch adds nothing
+        device.deliver(&second.encode(), second.message_id()).await;
+        let fin = FinPacket::new(device.id(), 6);
+        device.deliver(&fin.encode(), fin.message_id()).await;
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::ScanFinished { .. })).await;
+        assert_eq!(measurements(&seen), vec![(0, 0, 100), (0, 1, 101), (0, 2, 102), (1, 2, 103), (1, 1, 104), (1, 0, 105)]);
+        assert_eq!(seen.last(), Some(&SessionEvent::ScanFinished { received: 6, reported: 6, missing: 0 }));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_after_gap_not_acknowledged() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+
+        // First batch lost on the way, the second one has to wait for it
+        let first = batch(device.id(), 0, &[100, 101, 102]);
+        let second = batch(device.id(), 3, &[103, 104, 105]);
+        device.send(&second.encode()).await;
+        device.deliver(&first.encode(), first.message_id()).await;
+        device.deliver(&second.encode(), second.message_id()).await;
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::Measurement { index: 5, .. })).await;
+        assert_eq!(measurements(&seen), vec![(0, 0, 100), (0, 1, 101), (0, 2, 102), (1, 2, 103), (1, 1, 104), (1, 0, 105)]);
+        assert_eq!(session.state(), SessionState::Finishing);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn batch_carries_signal_status() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 1)).await;
+
+        let mut pack = MesBatchPacket::new(device.id(), 0);
+        pack.push(100, 900, MesStatus::Valid);
+        pack.push(0, 0, MesStatus::OutOfRange);
+        // Lidar overflow reported as a valid distance
+        pack.push(u32::MAX, 15, MesStatus::Valid);
+        device.deliver(&pack.encode(), pack.message_id()).await;
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::Measurement { index: 2, .. })).await;
+        let statuses: Vec<(u32, u16, MesStatus)> = seen.iter()
+            .filter_map(|event| match event {
+                SessionEvent::Measurement { index, strength, status, .. } => Some((*index, *strength, *status)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(statuses, vec![(0, 900, MesStatus::Valid), (1, 0, MesStatus::OutOfRange), (2, 15, MesStatus::OutOfRange)]);
+        assert_eq!(session.state(), SessionState::Finishing);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn duplicate_measurement_ignored() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(2, 1)).await;
+
+        // Ok of the first copy got lost, the device retransmits
+        let mes = MesPacket::new(device.id(), 0, 0, 42, 0, MesStatus::Valid);
+        device.deliver(&mes.encode(), mes.message_id()).await;
+        device.deliver(&mes.encode(), mes.message_id()).await;
+        // Same point measured again under a new message ID
+        device.mes(0, 0, 44).await;
+        device.mes(0, 1, 43).await;
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::StateChanged(SessionState::Finishing))).await;
+        assert_eq!(measurements(&seen), vec![(0, 0, 42), (0, 1, 43)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skipped_points_reported_missing() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+
+        device.mes(0, 0, 100).await;
+        let weak = MesPacket::new(device.id(), 0, 2, 102, 35, MesStatus::Weak);
+        device.deliver(&weak.encode(), weak.message_id()).await;
+        // Skipped point arriving late would shift nothing, it is rejected
+        device.mes(0, 1, 101).await;
+        device.mes(1, 2, 103).await;
+        device.mes(1, 1, 104).await;
+        // Last point lost, FIN tells the device measured it
+        let fin = FinPacket::new(device.id(), 6);
+        device.deliver(&fin.encode(), fin.message_id()).await;
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::ScanFinished { .. })).await;
+        assert_eq!(measurements(&seen), vec![(0, 0, 100), (0, 2, 102), (1, 2, 103), (1, 1, 104)]);
+        let missing: Vec<&SessionEvent> = seen.iter().filter(|event| matches!(event, SessionEvent::Missing { .. })).collect();
+        assert_eq!(missing, vec![&SessionEvent::Missing { index: 1, line: 0, point: 1 }, &SessionEvent::Missing { index: 5, line: 1, point: 0 }]);
+        assert!(seen.contains(&SessionEvent::Measurement { index: 2, line: 0, point: 2, distance: 102, strength: 35, status: MesStatus::Weak }));
+        assert_eq!(seen.last(), Some(&SessionEvent::ScanFinished { received: 6, reported: 6, missing: 2 }));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unsupported_version_faults() {
+        let (session, mut events, mut device) = setup();
+        let mut info = info(device.id());
+        info.protocol_version = PROTOCOL_VERSION - 1;
+
+        let (connect, _) = tokio::join!(session.connect(), device.answer_hello(info));
+        assert_eq!(connect, Err(SessionError::UnsupportedVersion(PROTOCOL_VERSION - 1)));
+        assert_eq!(session.state(), SessionState::Faulted);
+        until(&mut events, |event| matches!(event, SessionEvent::Fault(SessionError::UnsupportedVersion(_)))).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn busy_device_rejects_scan() {
+        let (session, _events, mut device) = connected().await;
+        let refuse = async {
+            let AnyPacket::Prog(prog) = device.recv().await else { panic!("expected prog") };
+            let err = ErrPacket::new(device.id(), ErrCode::BUSY, prog.message_id());
+            device.send(&err.encode()).await;
+        };
+        let (result, _) = tokio::join!(session.start_scan(ScanParams::new(3, 2)), refuse);
+        assert_eq!(result, Err(SessionError::Rejected("device busy")));
+        assert_eq!(session.state(), SessionState::Idle);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn oversized_scan_not_sent() {
+        let (session, _events, _device) = connected().await;
+        assert_eq!(session.start_scan(ScanParams::new(401, 2)).await, Err(SessionError::Unsupported("that many points per line")));
+        let mut params = ScanParams::new(3, 2);
+        params.microsteps = 4;
+        assert_eq!(session.start_scan(params).await, Err(SessionError::Unsupported("microstepping")));
+        assert_eq!(session.state(), SessionState::Idle);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_stops_scan() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+        device.mes(0, 0, 7).await;
+        device.mes(0, 1, 8).await;
+
+        let acknowledge = async {
+            let AnyPacket::Abort(abort) = device.recv().await else { panic!("expected abort") };
+            device.ack(abort.message_id()).await;
+        };
+        let (result, _) = tokio::join!(session.abort(), acknowledge);
+        result.unwrap();
+        assert_eq!(session.state(), SessionState::Aborted);
+        until(&mut events, |event| *event == SessionEvent::ScanAborted { received: 2 }).await;
+
+        // Late measurements are not part of any scan
+        device.mes(0, 2, 9).await;
+        assert_eq!(session.state(), SessionState::Aborted);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_retried_until_acknowledged() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+        device.mes(0, 0, 7).await;
+
+        let acknowledge_second = async {
+            // First copy lost on the way
+            let AnyPacket::Abort(first) = device.recv().await else { panic!("expected abort") };
+            let AnyPacket::Abort(second) = device.recv().await else { panic!("expected retransmitted abort") };
+            assert_eq!(first.message_id(), second.message_id());
+            device.ack(second.message_id()).await;
+        };
+        let (result, _) = tokio::join!(session.abort(), acknowledge_second);
+        result.unwrap();
+        assert_eq!(session.state(), SessionState::Aborted);
+        until(&mut events, |event| *event == SessionEvent::ScanAborted { received: 1 }).await;
+
+        // Next scan may start right away
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+        assert_eq!(session.state(), SessionState::Measuring);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resume_continues_after_last_point() {
+        let (session, mut events, mut device) = connected().await;
+        start(&session, &mut device, ScanParams::new(3, 2)).await;
+        let header = match until(&mut events, |event| matches!(event, SessionEvent::ScanStarted(_))).await.pop() {
+            Some(SessionEvent::ScanStarted(header)) => header,
+            _ => unreachable!(),
+        };
+        for (line, point, distance) in [(0, 0, 100), (0, 1, 101), (0, 2, 102), (1, 2, 103)] {
+            device.mes(line, point, distance).await;
+        }
+        let acknowledge = async {
+            let AnyPacket::Abort(abort) = device.recv().await else { panic!("expected abort") };
+            device.ack(abort.message_id()).await;
+        };
+        let (result, _) = tokio::join!(session.abort(), acknowledge);
+        result.unwrap();
+        until(&mut events, |event| *event == SessionEvent::ScanAborted { received: 4 }).await;
+
+        let program = async {
+            let AnyPacket::Resume(resume) = device.recv().await else { panic!("expected resume") };
+            assert_eq!((resume.start_line, resume.start_point), (1, 1));
+            device.ack(resume.message_id()).await;
+            let start = StartPacket::new(device.id(), resume.start_line, resume.start_point);
+            device.deliver(&start.encode(), start.message_id()).await;
+        };
+        let (result, _) = tokio::join!(session.resume_scan(header.clone(), 4), program);
+        result.unwrap();
+        assert_eq!(session.state(), SessionState::Measuring);
+
+        device.mes(1, 1, 104).await;
+        device.mes(1, 0, 105).await;
+        let fin = FinPacket::new(device.id(), 6);
+        device.deliver(&fin.encode(), fin.message_id()).await;
+
+        let seen = until(&mut events, |event| matches!(event, SessionEvent::ScanFinished { .. })).await;
+        assert!(seen.contains(&SessionEvent::ScanResumed { header: header.clone(), received: 4 }));
+        assert_eq!(measurements(&seen), vec![(1, 1, 104), (1, 0, 105)]);
+        assert_eq!(seen.last(), Some(&SessionEvent::ScanFinished { received: 6, reported: 6, missing: 0 }));
+
+        // Nothing left to resume
+        assert_eq!(session.resume_scan(header, 6).await, Err(SessionError::Complete));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_device_faults() {
+        let (session, mut events, mut device) = setup();
+        let (connect, hellos) = tokio::join!(session.connect(), async {
+            let mut hellos = 0;
+            while let Some(Ok(Ok(AnyPacket::Hello(_)))) = device.frames.next().await {
+                hellos += 1;
+                if hellos > TransportConfig::default().max_retries { break; }
+            }
+            hellos
+        });
+        assert_eq!(connect, Err(SessionError::NoResponse));
+        assert_eq!(hellos, TransportConfig::default().max_retries + 1);
+        assert_eq!(session.state(), SessionState::Faulted);
+        until(&mut events, |event| *event == SessionEvent::Fault(SessionError::NoResponse)).await;
+    }
+}
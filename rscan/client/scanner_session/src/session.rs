@@ -0,0 +1,92 @@
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, oneshot, watch};
+
+use scan_format::ScanHeader;
+use scanner_comms::packets::Axis;
+
+use crate::engine::Engine;
+use crate::{DeviceInfo, ScanParams, SessionConfig, SessionError, SessionEvent, SessionState};
+
+/// Commands passed from the handle to the session task.
+pub(crate) enum Command {
+    Connect(oneshot::Sender<Result<DeviceInfo, SessionError>>),
+    Move { axis: Axis, steps: i32, reply: oneshot::Sender<Result<(), SessionError>> },
+    StartScan { params: ScanParams, reply: oneshot::Sender<Result<(), SessionError>> },
+    Resume { header: ScanHeader, received: u32, reply: oneshot::Sender<Result<(), SessionError>> },
+    Abort(oneshot::Sender<Result<(), SessionError>>),
+}
+
+/// Handle of a session running the protocol with one device.
+///
+/// Cloned handles control the same session, the session task ends once all of them are dropped
+/// or the link closes.
+#[derive(Clone)]
+pub struct ScannerSession {
+    commands: mpsc::Sender<Command>,
+    state: watch::Receiver<SessionState>,
+}
+
+impl ScannerSession {
+    /// Starts the session task on the current tokio runtime
+    ///
+    /// io - byte stream connected to the device, usually the serial port
+    /// config - timing of the session
+    ///
+    /// @ret (ScannerSession, mpsc::UnboundedReceiver<SessionEvent>) - handle and the stream of events, no event is dropped
+    pub fn spawn<T>(io: T, config: SessionConfig) -> (Self, mpsc::UnboundedReceiver<SessionEvent>)
+    where
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (commands, command_rx) = mpsc::channel(4);
+        let (event_tx, events) = mpsc::unbounded_channel();
+        let (state_tx, state) = watch::channel(SessionState::Disconnected);
+
+        tokio::spawn(Engine::new(io, config, state_tx, event_tx).run(command_rx));
+        (ScannerSession { commands, state }, events)
+    }
+
+    /// Current state of the session
+    pub fn state(&self) -> SessionState {
+        *self.state.borrow()
+    }
+
+    /// Greets the device and checks it speaks our protocol
+    ///
+    /// @ret Result<DeviceInfo, SessionError> - description of the device
+    pub async fn connect(&self) -> Result<DeviceInfo, SessionError> {
+        self.call(Command::Connect).await
+    }
+
+    /// Moves a motor, negative steps turn counter-clockwise
+    pub async fn move_axis(&self, axis: Axis, steps: i32) -> Result<(), SessionError> {
+        self.call(|reply| Command::Move { axis, steps, reply }).await
+    }
+
+    /// Programs a scan, returns once the device accepted it
+    ///
+    /// The header of the scan arrives with `SessionEvent::ScanStarted`, the points with `SessionEvent::Measurement`.
+    pub async fn start_scan(&self, params: ScanParams) -> Result<(), SessionError> {
+        self.call(|reply| Command::StartScan { params, reply }).await
+    }
+
+    /// Continues an interrupted scan, returns once the device accepted it
+    ///
+    /// header - header of the interrupted scan, including its start position
+    /// received - number of points already stored, the scan continues with the next one
+    ///
+    /// The device confirms the position with `SessionEvent::ScanResumed`, the points continue with `SessionEvent::Measurement`.
+    pub async fn resume_scan(&self, header: ScanHeader, received: u32) -> Result<(), SessionError> {
+        self.call(|reply| Command::Resume { header, received, reply }).await
+    }
+
+    /// Aborts the running scan, returns once the device acknowledged it
+    pub async fn abort(&self) -> Result<(), SessionError> {
+        self.call(Command::Abort).await
+    }
+
+    async fn call<R>(&self, command: impl FnOnce(oneshot::Sender<Result<R, SessionError>>) -> Command) -> Result<R, SessionError> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(command(reply)).await.map_err(|_| SessionError::Closed)?;
+        response.await.map_err(|_| SessionError::Closed)?
+    }
+}
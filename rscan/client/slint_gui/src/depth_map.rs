@@ -0,0 +1,70 @@
+use scan_format::ScanHeader;
+
+use crate::preview::colormap;
+
+const EMPTY: [u8; 3] = [0x1c, 0x1c, 0x1c];
+
+/// False color image of the scan grid, one pixel per measured point.
+///
+/// points, lines - size of the grid
+/// cells - measured distances, row by row from the first line
+/// range - smallest and biggest distance measured so far
+pub struct DepthMap {
+    points: usize,
+    lines: usize,
+    cells: Vec<Option<u32>>,
+    range: Option<(u32, u32)>,
+}
+
+impl DepthMap {
+    pub fn new() -> Self {
+        DepthMap { points: 0, lines: 0, cells: Vec::new(), range: None }
+    }
+    /// Empty grid of the new scan
+    pub fn reset(&mut self, header: &ScanHeader) {
+        self.points = header.number_of_points as usize;
+        self.lines = header.number_of_lines as usize;
+        self.cells = vec![None; self.points * self.lines];
+        self.range = None;
+    }
+    /// Fills the cell of the received measurement, the position already follows the serpentine order
+    pub fn set(&mut self, line: u16, point: u16, distance: u32) {
+        let (line, point) = (line as usize, point as usize);
+        if line >= self.lines || point >= self.points { return; }
+        self.cells[line * self.points + point] = Some(distance);
+        self.range = Some(match self.range {
+            Some((min, max)) => (min.min(distance), max.max(distance)),
+            None => (distance, distance),
+        });
+    }
+    /// Smallest and biggest distance measured so far
+    pub fn range(&self) -> Option<(u32, u32)> {
+        self.range
+    }
+    /// Size of the rendered image
+    ///
+    /// @ret (u32, u32) - width and height in pixels, at least 1 x 1
+    pub fn size(&self) -> (u32, u32) {
+        (self.points.max(1) as u32, self.lines.max(1) as u32)
+    }
+    /// Renders the grid with the first line at the bottom, cells not measured yet stay dark
+    ///
+    /// @ret Vec<u8> - RGB8 pixels of `size()`
+    pub fn render(&self) -> Vec<u8> {
+        let (width, height) = self.size();
+        let mut pixels: Vec<u8> = EMPTY.iter().copied().cycle().take(width as usize * height as usize * 3).collect();
+        let (min, max) = match self.range {
+            Some(range) => range,
+            None => return pixels,
+        };
+
+        for (idx, cell) in self.cells.iter().enumerate() {
+            if let Some(distance) = cell {
+                let (line, point) = (idx / self.points, idx % self.points);
+                let pixel = (self.lines - 1 - line) * self.points + point;
+                pixels[pixel * 3..pixel * 3 + 3].copy_from_slice(&colormap(*distance, min, max));
+            }
+        }
+        pixels
+    }
+}
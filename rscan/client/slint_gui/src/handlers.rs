@@ -1,100 +1,72 @@
-use std::{io::Write, sync::{Arc, Mutex}};
 #[allow(unused_imports)]
 use log::{debug, error, info, warn};
-use scanner_comms::packets::packet_fin::FinPacket;
-use tokio::sync::mpsc::Sender;
-use scanner_comms::packets::Packet;
+use scan_format::{ScanHeader, ScanPoint};
+use scanner_comms::packets::MesStatus;
+use scanner_session::SessionError;
 
-use crate::state::GeneralState;
+use crate::state::ClientState;
 
-type CState = Arc<Mutex<super::state::ClientState>>;
-
-pub fn ok_pack(state: CState, pack: scanner_comms::packets::packet_ok::OkPacket) {
-    let mut state = state.lock().unwrap();
-    match state.ack {
-        super::state::AckState::Normal => warn!("Got unexpected ok for packet!"),
-        super::state::AckState::Awaiting => {
-            match state.general {
-                GeneralState::Programming => {
-                    let start_line = pack.sentinel;
-                    let start_point = pack.sentinel2;
-                    state.out_file.write_all(&[start_line, start_point]).unwrap();
-                    state.general = super::state::GeneralState::Measure;
-                    state.ack = super::state::AckState::Normal;
-                },
-                _ => { },
-            }
-            info!("Previous packet ok received!");
-            state.ack = super::state::AckState::Normal;
-            state.consec_error_counter = 0;
-        }
+pub fn start_handle(state: &mut ClientState, header: ScanHeader) {
+    info!("Scan starts at line {:?} point {:?}", header.start_line, header.start_point);
+    state.reset_step_cnt();
+    if let Err(e) = state.create_scan_file(header) {
+        error!("Scan file could not be created: {}", e);
     }
 }
 
-pub fn err_handle(state: CState, pack: scanner_comms::packets::packet_err::ErrPacket, send_chan: Sender<Vec<u8>>) {
-    let mut state = state.lock().unwrap();
-    match state.ack {
-        super::state::AckState::Normal => warn!("Got unexpected error for packet!"),
-        _ => (),
+pub fn resume_handle(state: &mut ClientState, header: &ScanHeader, received: u32) {
+    info!("Scan resumed at point {:?} of {:?}", received, header.expected_points());
+    if state.get_step_cnt() != received { warn!("Scan file holds {:?} points, device resumed at {:?}", state.get_step_cnt(), received) }
+}
+
+/// Stores the measurement and draws it into the views, distances without meaning are stored missing
+///
+/// @ret u32 - number of points received so far
+pub fn mes_handle(state: &mut ClientState, line: u16, point: u16, distance: u32, status: MesStatus) -> u32 {
+    if !status.has_distance() {
+        debug!("Point at line {:?} point {:?} not measured: {:?}", line, point, status);
+        return missing_handle(state, line, point);
     }
-    match pack.error {
-        scanner_comms::packets::ErrCode::BROKEN => {
-            state.consec_error_counter += 1;
-            if state.consec_error_counter >= 6 {
-                error!("Device replied with error BROKEN after 5 attempts! Loop has been broken! State is unknown, you continue on your own responsibility, here there be dragons!");
-                return ;
-            }
-            warn!("Packet reported broken: {:?}", state.last_pack);
-            tokio::task::block_in_place(|| {
-                let handle = tokio::runtime::Handle::current();
-                handle.block_on(send_chan.send(state.last_pack.clone())).unwrap();
-            });
-            warn!("Retransmitting...")
-        }
-        
-        scanner_comms::packets::ErrCode::UNKNOWN => {
-            error!("Received unknow error from the target!");
-        }
-        
-        scanner_comms::packets::ErrCode::BUSY => warn!("Target busy, belay command until target expects it."),
-    };
+    if let Some(scan) = state.scan.as_mut() {
+        if let Err(e) = scan.push(distance) { error!("Measurement not saved: {}", e); }
+    }
+    state.preview.push(&ScanPoint { line, point, distance });
+    state.depth_map.set(line, point, distance);
+    debug!("Wrote {:?} to file", distance);
+
+    state.make_step()
 }
 
-pub fn mes_handle(state: CState, pack: scanner_comms::packets::packet_mes::MesPacket, send_chan: Sender<Vec<u8>>, progress_chan: Sender<u16>) {
-    let mut state = state.lock().unwrap();
-    match state.general {
-        GeneralState::Measure => {
-            
-            state.out_file.write_all(&pack.mes.to_be_bytes()).unwrap();
-            
-            let stp = state.make_step() - 1;
-            
-            tokio::task::block_in_place(|| {
-                let handle = tokio::runtime::Handle::current();
-                handle.block_on(progress_chan.send(stp)).unwrap();
-            });
+/// Stores the marker of a point the device skipped, the views keep a hole there
+///
+/// @ret u32 - number of points received so far
+pub fn missing_handle(state: &mut ClientState, line: u16, point: u16) -> u32 {
+    if let Some(scan) = state.scan.as_mut() {
+        if let Err(e) = scan.push_missing() { error!("Missing point not saved: {}", e); }
+    }
+    warn!("Point at line {:?} point {:?} missing", line, point);
+
+    state.make_step()
+}
 
-            info!("Wrote {:?} to file", pack.mes);
-            let resp = scanner_comms::packets::packet_ok::OkPacket::new(123, 0xa0, 0x0a);
-            
-            let mut pack = Vec::with_capacity(10);
-        
-            let size = resp.serialize(pack.as_mut_ptr(), 10);
-            unsafe { pack.set_len(size); }
-            
-            state.ack = super::state::AckState::Awaiting;
-            tokio::task::block_in_place(|| {
-                let handle = tokio::runtime::Handle::current();
-                handle.block_on(send_chan.send(pack)).unwrap();
-            });
+pub fn fin_handle(state: &mut ClientState, reported: u32, missing: u32) {
+    if state.get_step_cnt() != reported { error!("Device reported {:?} points, got {:?}", reported, state.get_step_cnt()) }
+    if missing > 0 { warn!("Scan finished with {:?} points missing", missing) }
+    if let Some(scan) = state.scan.take() {
+        match scan.finish() {
+            Ok(_) => info!("Scan saved"),
+            Err(e) => error!("Scan file could not be finished: {}", e),
         }
-        _ => { error!("Unexpected mes! Measurement is ignored!"); }
     }
 }
 
-pub fn fin_handle(state: CState, pack: FinPacket) {
-    let mut state = state.lock().unwrap();
-    if state.get_step_cnt() != pack.number_of_points { error!("Some mes points lost. Got {:?}, expected {:?}", state.get_step_cnt(), pack.number_of_points) }
-    
-    state.general = GeneralState::Idle;
-}
\ No newline at end of file
+pub fn abort_handle(state: &mut ClientState, received: u32) {
+    warn!("Scan aborted after {:?} points", received);
+    if let Err(e) = state.abort_scan_file() { error!("Aborted scan could not be closed: {}", e); }
+}
+
+/// Leaves the partial scan unfinished, with the header telling how many points it holds
+pub fn fault_handle(state: &mut ClientState, e: &SessionError) {
+    error!("Connection with the device lost: {}", e);
+    if let Err(e) = state.suspend_scan_file() { error!("Scan file update failed: {}", e); }
+}
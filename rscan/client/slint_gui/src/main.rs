@@ -13,22 +13,22 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::{io::Write, sync::{Arc, Mutex}};
+use std::sync::{Arc, Mutex};
 
 use log::{debug, error, info, warn};
-use slint::{ComponentHandle, SharedString};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use slint::{ComponentHandle, Image, Rgb8Pixel, SharedPixelBuffer, SharedString};
 use tokio_serial::SerialPortBuilderExt;
 
-use scanner_comms::{self, packets::{Packet, RotSide}};
+use scan_export::geometry::ScannerGeometry;
+use scanner_comms::packets::Axis;
+use scanner_session::{ScannerSession, SessionConfig, SessionEvent, SessionState};
 
 slint::include_modules!();
 
 mod state;
-mod coder;
 mod handlers;
-
-const FRAME_END_TOKEN: u8 = 0x00;
+mod preview;
+mod depth_map;
 
 type CState = Arc<Mutex<state::ClientState>>;
 
@@ -46,15 +46,13 @@ async fn main() -> Result<(), slint::PlatformError> {
     let com_port = &args[1];
     let target_file = &args[2];
     let baud_rate = args[3].parse::<u32>().unwrap();
+    let geometry = match args.get(4) {
+        Some(path) => ScannerGeometry::load(path).unwrap(),
+        None => ScannerGeometry::default(),
+    };
     
     //let port = serial2_tokio::SerialPort::open(com_port, 115_200).unwrap();
-    let target_file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(target_file)
-        .unwrap();
-    
+
     let mut port = tokio_serial::new(com_port, baud_rate).open_native_async().unwrap();
     
     #[cfg(unix)]
@@ -63,155 +61,146 @@ async fn main() -> Result<(), slint::PlatformError> {
         
     info!("Opened port: {:?}", com_port);
     
-    let client_state: CState = Arc::new(Mutex::new(state::ClientState::new(target_file)));
+    let client_state: CState = Arc::new(Mutex::new(state::ClientState::new(target_file.into(), geometry)));
 
-    let (mut port_rx, mut port_tx) = tokio::io::split(port);
+    let (session, mut events) = ScannerSession::spawn(port, SessionConfig::default());
     
-    let (send_chan, mut client_rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
-    //let (device_tx, recv_chan) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
-    
-    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel::<u16>(32);
+    let (progress_tx, mut progress_rx) = tokio::sync::mpsc::channel::<u32>(32);
+    let (status_tx, mut status_rx) = tokio::sync::mpsc::channel::<String>(4);
+    let (interrupted_tx, mut interrupted_rx) = tokio::sync::mpsc::channel::<bool>(4);
+    let has_interrupted = client_state.lock().unwrap().has_interrupted_scan();
     
     debug!("Channels initialized!");
-    //let l_port = port.clone();
     let state_clone = client_state.clone();
     tokio::spawn(async move {
-        debug!("Spawned send thread");
-        loop {
-            let pack = client_rx.recv().await.unwrap();
-            
-            debug!("Got pack: {:?}", pack);
-            
-            //Skip saving if error:
-            if pack[4] != 2 {
-                let mut state = state_clone.lock().unwrap();
-                state.last_pack = pack.clone();
+        debug!("Spawned session event thread");
+        while let Some(event) = events.recv().await {
+            match event {
+                SessionEvent::StateChanged(state) => debug!("Session state: {:?}", state),
+                SessionEvent::Connected(device) => {
+                    info!("Connected to firmware {:?}, capabilities {:#06x}", device.fw_version, device.capabilities);
+                    let [major, minor, patch] = device.fw_version;
+                    status_tx.send(format!("connected, firmware {}.{}.{}, max {} points per line", major, minor, patch, device.max_steps_per_line)).await.unwrap();
+                }
+                SessionEvent::ScanStarted(header) => {
+                    handlers::start_handle(&mut state_clone.lock().unwrap(), header);
+                    interrupted_tx.send(false).await.unwrap();
+                }
+                SessionEvent::ScanResumed { header, received } => {
+                    handlers::resume_handle(&mut state_clone.lock().unwrap(), &header, received);
+                    status_tx.send(format!("scan resumed at point {}", received)).await.unwrap();
+                    progress_tx.send(received).await.unwrap();
+                }
+                SessionEvent::Measurement { line, point, distance, status, .. } => {
+                    let received = handlers::mes_handle(&mut state_clone.lock().unwrap(), line, point, distance, status);
+                    progress_tx.send(received).await.unwrap();
+                }
+                SessionEvent::Missing { line, point, .. } => {
+                    let received = handlers::missing_handle(&mut state_clone.lock().unwrap(), line, point);
+                    progress_tx.send(received).await.unwrap();
+                }
+                SessionEvent::ScanFinished { reported, missing, .. } => handlers::fin_handle(&mut state_clone.lock().unwrap(), reported, missing),
+                SessionEvent::ScanAborted { received } => {
+                    handlers::abort_handle(&mut state_clone.lock().unwrap(), received);
+                    status_tx.send(format!("scan aborted at point {}, partial scan kept", received)).await.unwrap();
+                    interrupted_tx.send(true).await.unwrap();
+                }
+                SessionEvent::Fault(e) => {
+                    let interrupted = {
+                        let mut state = state_clone.lock().unwrap();
+                        handlers::fault_handle(&mut state, &e);
+                        state.has_interrupted_scan()
+                    };
+                    status_tx.send(e.to_string()).await.unwrap();
+                    interrupted_tx.send(interrupted).await.unwrap();
+                }
             }
-            
-            debug!("Sent packet: {:?}", pack);
-            
-            port_tx.write(& pack).await.unwrap();
         }
+        error!("Session stopped");
     });
 
-    let send_chan_clone = send_chan.clone();
-    let state_clone = client_state.clone();
+    // Handshake, the device answers with INFO
+    let session_clone = session.clone();
     tokio::spawn(async move {
-        debug!("Spawned listener thread");
-        let mut buf: Vec<u8> = Vec::<u8>::new();
-        loop {
-            let val = port_rx.read_u8().await;
-            
-            match val {
-                Ok(val) => {
-                    buf.push(val);
-                    if val != FRAME_END_TOKEN { continue; }
-                    else {
-                        debug!("Got frame: {:?}", buf);
-                        let ret = coder::decode_packet(&mut buf);
-                        match ret {
-                            Err(_) => {
-                                warn!("Frame borked!");
-                                buf = Vec::<u8>::new();
-                                continue;
-                            }
-                            Ok(obj) => {
-                                match obj {
-                                    coder::PackType::Ok(pack) => handlers::ok_pack(state_clone.clone(), pack),
-                                    coder::PackType::Err(pack) => handlers::err_handle(state_clone.clone(), pack, send_chan_clone.clone()),
-                                    coder::PackType::Mes(pack) => handlers::mes_handle(state_clone.clone(), pack, send_chan_clone.clone(), progress_tx.clone()),
-                                    coder::PackType::Fin(pack) => handlers::fin_handle(state_clone.clone(), pack),
-                                    _ => println!("Other pack!"),
-                                };
-                            }
-                        }
-                        println!("Decoded {:?}", buf);
-                        buf = Vec::<u8>::new();
-                    }
-                }
-                Err(val) => {
-                    error!("We got error in send channel: {:?}", val);
-                }
-            }
+        if let Err(e) = session_clone.connect().await {
+            error!("Handshake failed: {}", e);
         }
     });
 
     let ui = MainAppWindow::new()?;
+    ui.set_interrupted(has_interrupted);
     
-    let tx_clone = send_chan.clone();
-    
+    let session_clone = session.clone();
     ui.on_send_abort_pack(move || {
-        let abort = scanner_comms::packets::packet_abort::AbortPacket::new(123);
-        let mut pack = Vec::with_capacity(7);
-        
-        let size = abort.serialize(pack.as_mut_ptr(), 11);
-        unsafe { pack.set_len(size); }
-        let tx_clone = tx_clone.clone();
-        let _ = slint::spawn_local(async move { tx_clone.send(pack).await.unwrap(); });
+        let session = session_clone.clone();
+        let _ = slint::spawn_local(async move {
+            if let Err(e) = session.abort().await { warn!("Abort failed: {}", e); }
+        });
     });
     
-    let tx_clone = send_chan.clone();
-    ui.on_pass_z_rot(move |number: SharedString| {
-        match number.parse::<i16>() {
-            Err(e) => warn!("Casting step value ended with error: {:?}", e)
-,           Ok(steps) => {
-                let mut mov = scanner_comms::packets::packet_mov::MovPacket::new(123, scanner_comms::packets::Axis::Horizon, RotSide::Clockwise, 0);
-                
-                let mut pack: Vec<u8> = Vec::with_capacity(11);
-                
-                if (0..=200).contains(&steps) {
-                    info!("Got {:?} steps Clockwise", steps);
-                    mov.steps = steps.try_into().expect("Something went very wrong!");
-                    mov.side = RotSide::Clockwise;
-                }
-                else if (-200..0).contains(&steps) {
-                    info!("Got {:?} steps Counter-clockwise", steps);
-                    mov.steps = steps.abs().try_into().expect("Something went very wrong!");
-                    mov.side = RotSide::CounterClockwise;
-                }
-                else { warn!("Value out of range for the device!"); return; };
-                
-                let size = mov.serialize(pack.as_mut_ptr(), 11);
-                unsafe { pack.set_len(size); }
-                let tx_clone = tx_clone.clone();
-                let _ = slint::spawn_local(async move { tx_clone.send(pack).await.unwrap(); });
-            }
+    let state_clone = client_state.clone();
+    let ui_handle = ui.as_weak();
+    ui.on_discard_scan(move || {
+        let mut state = state_clone.lock().unwrap();
+        if let Err(e) = state.discard_interrupted_scan() {
+            error!("Interrupted scan could not be deleted: {}", e);
+            return;
         }
+        let handle = ui_handle.unwrap();
+        handle.set_interrupted(false);
+        handle.set_progress(0.0);
+        handle.set_raw_progress(SharedString::from(format!("0/{:?}", state.get_total_steps())));
+        handle.set_device_status(SharedString::from("interrupted scan discarded"));
     });
     
-    let tx_clone = send_chan.clone();
-    ui.on_pass_x_rot(move |number: SharedString| {
-        match number.parse::<i16>() {
-            Err(e) => warn!("Casting step value ended with error: {:?}", e)
-,           Ok(steps) => {
-                let mut mov = scanner_comms::packets::packet_mov::MovPacket::new(123, scanner_comms::packets::Axis::Azimuth, RotSide::Clockwise, 0);
-                
-                let mut pack: Vec<u8> = Vec::with_capacity(11);
-                
-                if (0..=200).contains(&steps) {
-                    info!("Got {:?} steps Clockwise", steps);
-                    mov.steps = steps.try_into().expect("Something went very wrong!");
-                    mov.side = RotSide::Clockwise;
-                }
-                else if (-200..0).contains(&steps) {
-                    info!("Got {:?} steps Counter-clockwise", steps);
-                    mov.steps = steps.abs().try_into().expect("Something went very wrong!");
-                    mov.side = RotSide::CounterClockwise;
-                }
-                else { warn!("Value out of range for the device!"); return; };
-                
-                let size = mov.serialize(pack.as_mut_ptr(), 11);
-                unsafe { pack.set_len(size); }
-                let tx_clone = tx_clone.clone();
-                let _ = slint::spawn_local(async move { tx_clone.send(pack).await.unwrap(); });
+    let state_clone = client_state.clone();
+    let session_clone = session.clone();
+    let ui_handle = ui.as_weak();
+    ui.on_resume_scan(move || {
+        let reopened = state_clone.lock().unwrap().reopen_scan_file();
+        let header = match reopened {
+            Ok(header) => header,
+            Err(e) => {
+                warn!("Scan cannot be resumed: {}", e);
+                ui_handle.unwrap().set_device_status(SharedString::from(format!("cannot resume: {}", e)));
+                return;
             }
-        }
+        };
+        ui_handle.unwrap().set_interrupted(false);
+        let state = state_clone.clone();
+        let session = session_clone.clone();
+        let ui_handle = ui_handle.clone();
+        let _ = slint::spawn_local(async move {
+            // Device restarted meanwhile, the handshake comes first
+            let mut result = Ok(());
+            if matches!(session.state(), SessionState::Disconnected | SessionState::Faulted) {
+                result = session.connect().await.map(|_| ());
+            }
+            if result.is_ok() {
+                let received = state.lock().unwrap().get_step_cnt();
+                result = session.resume_scan(header, received).await;
+            }
+            if let Err(e) = result {
+                warn!("Scan not resumed: {}", e);
+                let mut state = state.lock().unwrap();
+                if let Err(e) = state.suspend_scan_file() { error!("Scan file update failed: {}", e); }
+                let handle = ui_handle.unwrap();
+                handle.set_interrupted(state.has_interrupted_scan());
+                handle.set_device_status(SharedString::from(format!("cannot resume: {}", e)));
+            }
+        });
     });
     
+    let session_clone = session.clone();
+    ui.on_pass_z_rot(move |number: SharedString| move_axis(&session_clone, Axis::Horizon, number));
+    
+    let session_clone = session.clone();
+    ui.on_pass_x_rot(move |number: SharedString| move_axis(&session_clone, Axis::Azimuth, number));
+    
     let state_clone = client_state.clone();
     ui.on_read_steps_update(move |number: SharedString|{
         debug!("Updated string to: {:?}", number);
-        match number.parse::<u8>() {
+        match number.parse::<u16>() {
             Err(e) => { warn!("Value cannto be cast due to: {:?}", e); return; },
             Ok(steps) => {
                 let mut state = state_clone.lock().unwrap();
@@ -223,7 +212,7 @@ async fn main() -> Result<(), slint::PlatformError> {
     let state_clone = client_state.clone();
     ui.on_read_lines_update(move |number: SharedString|{
         debug!("Updated string to: {:?}", number);
-        match number.parse::<u8>() {
+        match number.parse::<u16>() {
             Err(e) => { warn!("Value cannto be cast due to: {:?}", e); return; },
             Ok(steps) => {
                 let mut state = state_clone.lock().unwrap();
@@ -233,22 +222,39 @@ async fn main() -> Result<(), slint::PlatformError> {
     });
     
     let state_clone = client_state.clone();
-    let tx_clone = send_chan.clone();
+    ui.on_read_point_resolution_update(move |number: SharedString|{
+        debug!("Updated string to: {:?}", number);
+        match number.parse::<u16>() {
+            Err(e) => warn!("Value cannto be cast due to: {:?}", e),
+            Ok(0) => warn!("Step size has to be at least 1!"),
+            Ok(step_size) => {
+                let mut state = state_clone.lock().unwrap();
+                state.set_point_step_size(step_size);
+            }
+        }
+    });
+    
+    let state_clone = client_state.clone();
+    ui.on_read_line_resolution_update(move |number: SharedString|{
+        debug!("Updated string to: {:?}", number);
+        match number.parse::<u16>() {
+            Err(e) => warn!("Value cannto be cast due to: {:?}", e),
+            Ok(0) => warn!("Step size has to be at least 1!"),
+            Ok(step_size) => {
+                let mut state = state_clone.lock().unwrap();
+                state.set_line_step_size(step_size);
+            }
+        }
+    });
+    
+    let state_clone = client_state.clone();
+    let session_clone = session.clone();
     ui.on_send_prog_pack(move || {
-        let mut state = state_clone.lock().unwrap();
-        let abort = scanner_comms::packets::packet_prog::ProgPacket::new(123, state.get_steps(), state.get_lines());
-        let mut pack = Vec::with_capacity(11);
-        
-        let size = abort.serialize(pack.as_mut_ptr(), 11);
-        unsafe { pack.set_len(size); }
-        let tx_clone = tx_clone.clone();
-        let _ = slint::spawn_local(async move { tx_clone.send(pack).await.unwrap(); });
-        
-        let lines = state.get_lines();
-        let steps = state.get_steps();
-        state.ack = state::AckState::Awaiting;
-        state.general = state::GeneralState::Programming;
-        state.out_file.write_all(&[0x01, 0x01, lines, steps]).unwrap();
+        let params = state_clone.lock().unwrap().scan_params();
+        let session = session_clone.clone();
+        let _ = slint::spawn_local(async move {
+            if let Err(e) = session.start_scan(params).await { warn!("Scan not started: {}", e); }
+        });
     });
     
     let ui_handle = ui.as_weak();
@@ -256,13 +262,78 @@ async fn main() -> Result<(), slint::PlatformError> {
     
     tokio::spawn(async move {
         loop {
-            let raw_progress = progress_rx.recv().await.unwrap();
+            let mut raw_progress = progress_rx.recv().await.unwrap();
+            // Points arriving faster than the UI redraws are shown together
+            while let Ok(newer) = progress_rx.try_recv() {
+                raw_progress = newer;
+            }
             let state = state_clone.lock().unwrap();
             let total = state.get_total_steps();
             let progress = raw_progress as f32 / state.get_total_steps() as f32;
+            let (width, height) = state.depth_map.size();
+            let depth = SharedPixelBuffer::<Rgb8Pixel>::clone_from_slice(&state.depth_map.render(), width, height);
+            let (min, max) = match state.depth_map.range() {
+                Some((min, max)) => (min.to_string(), max.to_string()),
+                None => ("-".to_string(), "-".to_string()),
+            };
             ui_handle.upgrade_in_event_loop(move |handle| {
                 handle.set_progress(progress);
                 handle.set_raw_progress(SharedString::from(format!("{:?}/{:?}", raw_progress, total)));
+                handle.set_depth_map(Image::from_rgb8(depth));
+                handle.set_depth_min(SharedString::from(min));
+                handle.set_depth_max(SharedString::from(max));
+            }).unwrap();
+        }
+    });
+    
+    let state_clone = client_state.clone();
+    ui.on_preview_orbit(move |dx: f32, dy: f32| {
+        state_clone.lock().unwrap().preview.orbit(dx, dy);
+    });
+
+    let state_clone = client_state.clone();
+    ui.on_preview_zoom(move |factor: f32| {
+        state_clone.lock().unwrap().preview.zoom(factor);
+    });
+
+    let state_clone = client_state.clone();
+    ui.on_preview_reset(move || {
+        state_clone.lock().unwrap().preview.reset_view();
+    });
+
+    // Preview is redrawn at most 10 times a second, rendering every point would stall the listener
+    let ui_handle = ui.as_weak();
+    let state_clone = client_state.clone();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_millis(100));
+        loop {
+            ticker.tick().await;
+            let pixels = {
+                let mut state = state_clone.lock().unwrap();
+                if !state.preview.take_dirty() { continue; }
+                state.preview.render(preview::PREVIEW_WIDTH, preview::PREVIEW_HEIGHT)
+            };
+            let buffer = SharedPixelBuffer::<Rgb8Pixel>::clone_from_slice(&pixels, preview::PREVIEW_WIDTH, preview::PREVIEW_HEIGHT);
+            ui_handle.upgrade_in_event_loop(move |handle| {
+                handle.set_preview(Image::from_rgb8(buffer));
+            }).unwrap();
+        }
+    });
+
+    let ui_handle = ui.as_weak();
+    tokio::spawn(async move {
+        while let Some(interrupted) = interrupted_rx.recv().await {
+            ui_handle.upgrade_in_event_loop(move |handle| {
+                handle.set_interrupted(interrupted);
+            }).unwrap();
+        }
+    });
+
+    let ui_handle = ui.as_weak();
+    tokio::spawn(async move {
+        while let Some(status) = status_rx.recv().await {
+            ui_handle.upgrade_in_event_loop(move |handle| {
+                handle.set_device_status(SharedString::from(status));
             }).unwrap();
         }
     });
@@ -291,3 +362,21 @@ async fn main() -> Result<(), slint::PlatformError> {
     }
     */
 }
+
+/// Parses the step count typed into the UI and moves the motor
+fn move_axis(session: &ScannerSession, axis: Axis, number: SharedString) {
+    match number.parse::<i32>() {
+        Err(e) => warn!("Casting step value ended with error: {:?}", e),
+        Ok(steps) => {
+            if steps < 0 {
+                info!("Got {:?} steps Counter-clockwise", steps.unsigned_abs());
+            } else {
+                info!("Got {:?} steps Clockwise", steps);
+            }
+            let session = session.clone();
+            let _ = slint::spawn_local(async move {
+                if let Err(e) = session.move_axis(axis, steps).await { warn!("Move failed: {}", e); }
+            });
+        }
+    }
+}
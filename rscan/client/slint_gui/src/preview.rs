@@ -0,0 +1,133 @@
+use scan_export::geometry::ScannerGeometry;
+use scan_format::{ScanHeader, ScanPoint};
+
+/// Size of the rendered preview in pixels
+pub const PREVIEW_WIDTH: u32 = 480;
+pub const PREVIEW_HEIGHT: u32 = 320;
+
+const BACKGROUND: [u8; 3] = [0x1c, 0x1c, 0x1c];
+
+/// Live point cloud of the running scan, software rendered.
+///
+/// geometry - scanner model projecting the measurements
+/// points - projected points with their raw distance
+/// yaw, pitch - camera orbit around the cloud center in degrees
+/// zoom - magnification, 1 fits the whole cloud
+/// dirty - points or camera changed since the last render
+pub struct Preview {
+    geometry: ScannerGeometry,
+    points: Vec<([f32; 3], u32)>,
+    yaw: f32,
+    pitch: f32,
+    zoom: f32,
+    dirty: bool,
+}
+
+impl Preview {
+    pub fn new(geometry: ScannerGeometry) -> Self {
+        Preview { geometry, points: Vec::new(), yaw: 0.0, pitch: 15.0, zoom: 1.0, dirty: true }
+    }
+    /// Drops the points of the previous scan, the scan header provides the step angles
+    pub fn reset(&mut self, header: &ScanHeader) {
+        self.geometry = self.geometry.with_header(header);
+        self.points.clear();
+        self.dirty = true;
+    }
+    /// Adds the received measurement
+    pub fn push(&mut self, point: &ScanPoint) {
+        self.points.push((self.geometry.project(point), point.distance));
+        self.dirty = true;
+    }
+    /// Turns the camera around the cloud
+    ///
+    /// dx, dy - mouse drag in pixels
+    pub fn orbit(&mut self, dx: f32, dy: f32) {
+        self.yaw = (self.yaw + dx * 0.5) % 360.0;
+        self.pitch = (self.pitch + dy * 0.5).clamp(-89.0, 89.0);
+        self.dirty = true;
+    }
+    /// Changes the magnification
+    ///
+    /// factor - multiplier of the current zoom
+    pub fn zoom(&mut self, factor: f32) {
+        self.zoom = (self.zoom * factor).clamp(0.1, 50.0);
+        self.dirty = true;
+    }
+    /// Default camera
+    pub fn reset_view(&mut self) {
+        self.yaw = 0.0;
+        self.pitch = 15.0;
+        self.zoom = 1.0;
+        self.dirty = true;
+    }
+    /// Checks and clears the change flag
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+    /// Renders the cloud colored by the raw distance, near points cover the far ones
+    ///
+    /// @ret Vec<u8> - RGB8 pixels, `width * height * 3` bytes
+    pub fn render(&self, width: u32, height: u32) -> Vec<u8> {
+        let (w, h) = (width as usize, height as usize);
+        let mut pixels: Vec<u8> = BACKGROUND.iter().copied().cycle().take(w * h * 3).collect();
+        if self.points.is_empty() { return pixels; }
+
+        // Bounding sphere of the cloud
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for (p, _) in &self.points {
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        let center = [0, 1, 2].map(|i| (min[i] + max[i]) / 2.0);
+        let radius = [0, 1, 2].map(|i| max[i] - center[i]).iter().map(|v| v * v).sum::<f32>().sqrt().max(1e-3);
+
+        let near = self.points.iter().map(|(_, d)| *d).min().unwrap_or(0);
+        let far = self.points.iter().map(|(_, d)| *d).max().unwrap_or(0);
+
+        // Camera behind the scanner looking along Y, perspective fitting the sphere at zoom 1
+        let (sin_yaw, cos_yaw) = self.yaw.to_radians().sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.to_radians().sin_cos();
+        let camera = 3.0 * radius;
+        let focal = 0.9 * (w.min(h) as f32 / 2.0) * (camera - radius) / radius * self.zoom;
+        let size = if self.points.len() < 5000 { 2 } else { 1 };
+
+        let mut depth = vec![f32::MAX; w * h];
+        for (p, distance) in &self.points {
+            let v = [p[0] - center[0], p[1] - center[1], p[2] - center[2]];
+            let v = [v[0] * cos_yaw - v[1] * sin_yaw, v[0] * sin_yaw + v[1] * cos_yaw, v[2]];
+            let v = [v[0], v[1] * cos_pitch + v[2] * sin_pitch, -v[1] * sin_pitch + v[2] * cos_pitch];
+
+            let z = v[1] + camera;
+            if z <= 1e-3 { continue; }
+            let sx = w as f32 / 2.0 + v[0] * focal / z;
+            let sy = h as f32 / 2.0 - v[2] * focal / z;
+            if !(0.0..w as f32).contains(&sx) || !(0.0..h as f32).contains(&sy) { continue; }
+
+            let color = colormap(*distance, near, far);
+            let (sx, sy) = (sx as usize, sy as usize);
+            for y in sy..(sy + size).min(h) {
+                for x in sx..(sx + size).min(w) {
+                    let idx = y * w + x;
+                    if z < depth[idx] {
+                        depth[idx] = z;
+                        pixels[idx * 3..idx * 3 + 3].copy_from_slice(&color);
+                    }
+                }
+            }
+        }
+        pixels
+    }
+}
+
+/// Blue for the nearest, through green, to red for the farthest distance
+pub fn colormap(distance: u32, near: u32, far: u32) -> [u8; 3] {
+    let t = if far > near { (distance.saturating_sub(near)) as f32 / (far - near) as f32 } else { 0.5 };
+    let t = t.clamp(0.0, 1.0);
+    let r = ((t - 0.5) * 2.0).clamp(0.0, 1.0);
+    let g = 1.0 - ((t - 0.5).abs() * 2.0);
+    let b = ((0.5 - t) * 2.0).clamp(0.0, 1.0);
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
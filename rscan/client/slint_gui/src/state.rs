@@ -1,78 +1,223 @@
-use log::debug;
+use std::path::{Path, PathBuf};
 
-pub enum GeneralState {
-    Idle,
-    Programming,
-    Measure
-}
+use anyhow::{anyhow, bail};
+use log::{debug, info};
+use scan_export::geometry::ScannerGeometry;
+use scan_format::{FormatError, ScanHeader, ScanReader, ScanWriter};
+use scanner_session::ScanParams;
 
-pub enum AckState {
-    Normal,
-    Awaiting,
-}
+use crate::depth_map::DepthMap;
+use crate::preview::Preview;
 
 struct MState {
-    steps: u8,
-    lines: u8,
-    total_steps: u16,
-    current_step: u16,
+    steps: u16,
+    lines: u16,
+    point_step_size: u16,
+    line_step_size: u16,
+    microsteps: u8,
+    total_steps: u32,
+    current_step: u32,
 }
 
 pub struct ClientState {
-    pub general: GeneralState,
-    pub ack: AckState,
-    pub last_pack: Vec<u8>,
-    pub consec_error_counter: u8,
     mes_state: MState,
-    pub out_file: std::fs::File,
+    out_path: PathBuf,
+    scan_path: Option<PathBuf>,
+    interrupted_path: Option<PathBuf>,
+    pub scan: Option<ScanWriter<std::fs::File>>,
+    pub preview: Preview,
+    pub depth_map: DepthMap,
 }
 
 impl ClientState {
-    pub fn new(out_file: std::fs::File) -> Self {
+    pub fn new(out_path: PathBuf, geometry: ScannerGeometry) -> Self {
+        // Scan left behind by the previous run can be resumed
+        let interrupted_path = find_interrupted(&out_path);
+        if let Some(path) = &interrupted_path {
+            info!("Found interrupted scan {:?}", path);
+        }
         ClientState {
-            general: GeneralState::Idle,
-            ack: AckState::Normal,
-            last_pack: Vec::<u8>::new(),
-            consec_error_counter: 0,
             mes_state: MState {
                 steps: 0,
                 lines: 0,
+                point_step_size: 1,
+                line_step_size: 1,
+                microsteps: 0,
                 total_steps: 0,
                 current_step: 0,
             },
-            out_file,
+            out_path,
+            scan_path: None,
+            interrupted_path,
+            scan: None,
+            preview: Preview::new(geometry),
+            depth_map: DepthMap::new(),
+        }
+    }
+    /// Creates the file of the started scan, never overwrites previous scans
+    ///
+    /// header - the scan as reported by the session
+    pub fn create_scan_file(&mut self, header: ScanHeader) -> Result<(), FormatError> {
+        self.preview.reset(&header);
+        self.depth_map.reset(&header);
+        let path = free_path(&self.out_path);
+        info!("Writing scan to {:?}", path);
+        self.scan = Some(ScanWriter::create(&path, header)?);
+        self.scan_path = Some(path);
+        // Starting a new scan keeps the previous interrupted one
+        self.interrupted_path = None;
+        Ok(())
+    }
+    /// Reopens the interrupted scan to continue it, the stored points are drawn again
+    ///
+    /// The scan has to match the parameters set in the UI.
+    ///
+    /// @ret ScanHeader - the scan to resume, its point count is the index of the next point
+    pub fn reopen_scan_file(&mut self) -> anyhow::Result<ScanHeader> {
+        let path = self.interrupted_path.clone().ok_or_else(|| anyhow!("no interrupted scan"))?;
+        let stored = ScanReader::open(&path)?;
+        if ScanParams::from(stored.header()) != self.scan_params() {
+            bail!("scan parameters differ from the interrupted scan");
+        }
+        drop(stored);
+
+        let scan = ScanWriter::resume(&path)?;
+        let header = scan.header().clone();
+        self.preview.reset(&header);
+        self.depth_map.reset(&header);
+        for point in ScanReader::open(&path)? {
+            let point = point?;
+            if point.is_missing() { continue; }
+            self.preview.push(&point);
+            self.depth_map.set(point.line, point.point, point.distance);
+        }
+        info!("Resuming scan {:?} at point {:?}", path, header.point_count);
+        self.mes_state.current_step = header.point_count;
+        self.scan = Some(scan);
+        self.scan_path = Some(path);
+        self.interrupted_path = None;
+        Ok(header)
+    }
+    /// Closes the file of the aborted scan with the trailer, the file is kept until discarded
+    pub fn abort_scan_file(&mut self) -> Result<(), FormatError> {
+        self.interrupted_path = self.scan_path.take();
+        match self.scan.take() {
+            Some(scan) => scan.abort().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+    /// Leaves the file of the interrupted scan unfinished, the header tells how many points it holds
+    pub fn suspend_scan_file(&mut self) -> Result<(), FormatError> {
+        self.interrupted_path = self.scan_path.take();
+        match self.scan.take() {
+            Some(mut scan) => scan.sync_header(),
+            None => Ok(()),
+        }
+    }
+    /// Checks if there is a scan to resume or discard
+    pub fn has_interrupted_scan(&self) -> bool {
+        self.interrupted_path.is_some()
+    }
+    /// Deletes the file of the last interrupted scan
+    pub fn discard_interrupted_scan(&mut self) -> std::io::Result<()> {
+        self.reset_step_cnt();
+        match self.interrupted_path.take() {
+            Some(path) => {
+                info!("Discarding scan {:?}", path);
+                std::fs::remove_file(path)
+            }
+            None => Ok(()),
+        }
+    }
+    /// Parameters of the next scan as set in the UI
+    pub fn scan_params(&self) -> ScanParams {
+        ScanParams {
+            number_of_points: self.mes_state.steps,
+            number_of_lines: self.mes_state.lines,
+            point_step_size: self.mes_state.point_step_size,
+            line_step_size: self.mes_state.line_step_size,
+            microsteps: self.mes_state.microsteps,
         }
     }
-    pub fn set_steps(&mut self, steps: u8) {
+    pub fn set_steps(&mut self, steps: u16) {
         self.mes_state.steps = steps;
-        self.mes_state.total_steps = self.mes_state.steps as u16 * self.mes_state.lines as u16;
+        self.mes_state.total_steps = self.mes_state.steps as u32 * self.mes_state.lines as u32;
         debug!("Points set to: {:?} total steps {:?}", self.mes_state.steps, self.mes_state.total_steps);
     }
-    pub fn get_steps(&self) -> u8 {
-        self.mes_state.steps
-    }
-    pub fn set_lines(&mut self, lines: u8) {
+    pub fn set_lines(&mut self, lines: u16) {
         self.mes_state.lines = lines;
-        self.mes_state.total_steps = self.mes_state.steps as u16 * self.mes_state.lines as u16;
+        self.mes_state.total_steps = self.mes_state.steps as u32 * self.mes_state.lines as u32;
         debug!("Lines set to: {:?} total steps {:?}", self.mes_state.steps, self.mes_state.total_steps);
     }
-    pub fn get_lines(&self) -> u8 {
-        self.mes_state.lines
+    pub fn set_point_step_size(&mut self, step_size: u16) {
+        self.mes_state.point_step_size = step_size;
+        debug!("Point step size set to: {:?}", step_size);
     }
-    pub fn get_total_steps(&self) -> u16 {
+    pub fn set_line_step_size(&mut self, step_size: u16) {
+        self.mes_state.line_step_size = step_size;
+        debug!("Line step size set to: {:?}", step_size);
+    }
+    pub fn get_total_steps(&self) -> u32 {
         self.mes_state.total_steps
     }
-    pub fn make_step(&mut self) -> u16 {
+    pub fn make_step(&mut self) -> u32 {
         self.mes_state.current_step += 1;
         debug!("Registered step");
         self.mes_state.current_step
     }
-    #[allow(dead_code)]
     pub fn reset_step_cnt(&mut self) {
         self.mes_state.current_step = 0;
         debug!("Step counter reset");
     }
-    pub fn get_step_cnt(&self) -> u16 {
+    pub fn get_step_cnt(&self) -> u32 {
         self.mes_state.current_step
     }
-}
\ No newline at end of file
+}
+
+/// Newest unfinished or aborted scan among `path`, `path-1`, `path-2`...
+///
+/// The whole directory is searched, discarded scans leave gaps in the numbering.
+fn find_interrupted(path: &Path) -> Option<PathBuf> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|candidate| in_series(path, candidate))
+        .filter_map(|candidate| {
+            let reader = ScanReader::open(&candidate).ok()?;
+            let header = reader.header();
+            (!header.is_finished() || header.is_aborted()).then_some((header.started_at, candidate))
+        })
+        .max_by_key(|(started_at, _)| *started_at)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Checks if the file is `path` or one of the numbered paths free_path gives
+fn in_series(path: &Path, candidate: &Path) -> bool {
+    if candidate.extension() != path.extension() { return false; }
+    let (stem, name) = match (path.file_stem().and_then(|s| s.to_str()), candidate.file_stem().and_then(|s| s.to_str())) {
+        (Some(stem), Some(name)) => (stem, name),
+        _ => return false,
+    };
+    match name.strip_prefix(stem) {
+        Some("") => true,
+        Some(rest) => rest.strip_prefix('-').is_some_and(|cnt| !cnt.is_empty() && cnt.bytes().all(|b| b.is_ascii_digit())),
+        None => false,
+    }
+}
+
+/// First of `path`, `path-1`, `path-2`... that does not exist yet
+fn free_path(path: &Path) -> PathBuf {
+    let mut candidate = path.to_path_buf();
+    let mut cnt = 0;
+    while candidate.exists() {
+        cnt += 1;
+        let mut name = path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!("-{}", cnt));
+        candidate = path.with_file_name(name);
+        if let Some(ext) = path.extension() { candidate.set_extension(ext); }
+    }
+    candidate
+}
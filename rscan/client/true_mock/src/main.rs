@@ -1,8 +1,10 @@
 use std::{io::{Read, Write}, ops::Deref};
-use scanner_comms::packets::Packet;
+use scanner_comms::accumulator::{FeedResult, FrameAccumulator};
+use scanner_comms::packets::packet_mes_batch::MesBatchPacket;
+use scanner_comms::packets::{AnyPacket, DecodeError, MesStatus, Packet, CAP_ABORT, CAP_MICROSTEPPING, CAP_RESUME, MAX_FRAME_SIZE, MES_BATCH_MAX_POINTS, MES_BATCH_WINDOW};
+use scanner_comms::transport::{DuplicateFilter, MessageIdAllocator, ReliableSender, TransportConfig, TransportEvent};
 use serialport::SerialPort;
 
-mod coder;
 
 enum State {
     Idle,
@@ -21,75 +23,185 @@ fn main() {
     let mut ack = AckState::Send;
     
     let mut mock_data = Vec::<u32>::new();
-    let mut mock_iter = 0;
     
     let com_port = &args[1];
     let dur = args[2].parse::<u64>().unwrap();
+    // Points per MES_BATCH, 1 sends every point in its own MES
+    let batch_size = args.get(3).map_or(1, |size| size.parse::<usize>().unwrap()).clamp(1, MES_BATCH_MAX_POINTS);
     
     let mut port = serialport::new(com_port, 115_200).open_native().unwrap();
     
-    port.set_timeout(std::time::Duration::from_secs(30)).unwrap();
+    let config = TransportConfig::default();
+    let mut transport = ReliableSender::<MES_BATCH_WINDOW>::new(config);
+    let clock = std::time::Instant::now();
+    let mut ids = MessageIdAllocator::new(clock_seed());
+    let mut rx_filter = DuplicateFilter::<8>::new();
+    
+    port.set_timeout(std::time::Duration::from_millis(config.timeout_ms as u64)).unwrap();
     
     #[cfg(unix)]
     port.set_exclusive(false)
         .expect("Unable to set serial port exclusive to false");
         
-    let mut buf = Vec::<u8>::new();
+    let mut reader = FrameReader::new();
     loop {
-        let mut tbuf = [0u8; 1];
-        let _ = port.read(&mut tbuf).unwrap();
-        buf.push(tbuf[0]);
-        if tbuf[0] != 0x00 { continue; }
-        
-        match coder::decode_packet(&mut buf){
-            Err(_) => {
-                println!("Frame broken!");
-                buf = Vec::<u8>::new();
+        let obj = match reader.next_packet(&mut port) {
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+            Err(e) => panic!("Serial port error: {:?}", e),
+            Ok(obj) => obj,
+        };
+        match obj {
+            Err(e) => {
+                println!("Frame broken: {}", e);
                 continue;
             },
             Ok(obj) => {
+                if !matches!(obj, AnyPacket::Ok(_) | AnyPacket::Err(_)) && !rx_filter.check(obj.message_id()) {
+                    // Client did not get our ok, acknowledge again without executing
+                    println!("Duplicate packet {:?} ignored", obj.message_id());
+                    let resp = scanner_comms::packets::packet_ok::OkPacket::new(ids.next_id(), obj.message_id());
+                    port.write_all(&resp.encode()).unwrap();
+                    continue;
+                }
                 match obj {
-                    coder::PackType::Prog(pack) => {
+                    AnyPacket::Prog(_) | AnyPacket::Resume(_) => {
+                        let request = ScanRequest::from_packet(&obj);
                         match state {
                             State::Idle => {
                                 state = State::Measure;
-                                println!("Got scan request!");
-                                mock_data = gen_data_points(pack.number_of_lines, pack.number_of_points);
-                                let resp = scanner_comms::packets::packet_ok::OkPacket::new(123, 0x00, 0x00);
+                                println!("Got scan request! Step sizes {:?}/{:?}, microsteps {:?}", request.point_step_size, request.line_step_size, request.microsteps);
+                                mock_data = gen_data_points(request.number_of_lines, request.number_of_points);
+                                let resp = scanner_comms::packets::packet_ok::OkPacket::new(ids.next_id(), obj.message_id());
                                 
-                                let mut pack = Vec::with_capacity(10);
-        
-                                let size = resp.serialize(pack.as_mut_ptr(), 10);
-                                unsafe { pack.set_len(size); }
+                                let pack = resp.encode();
                                 port.write_all(&pack).unwrap();
                                 
-                                for element in mock_data {
-                                    println!("Sending mock point");
-                                    let mes = scanner_comms::packets::packet_mes::MesPacket::new(123, element);
+                                // Mock always scans from the origin, resumed scan continues at the requested point
+                                let first = request.first_index();
+                                let (start_line, start_point) = request.start;
+                                println!("Starting at line {:?} point {:?}", start_line, start_point);
+                                let start = scanner_comms::packets::packet_start::StartPacket::new(ids.next_id(), start_line, start_point);
+                                let pack = start.encode();
+                                port.write_all(&pack).unwrap();
+                                transport.track(start.message_id(), &pack, clock.elapsed().as_millis() as u32).unwrap();
+                                match await_ack(&mut port, &mut reader, &mut transport, &mut ids, &mut rx_filter, &clock, 0) {
+                                    Delivery::Acked => (),
+                                    Delivery::Lost => {
+                                        println!("Client does not respond, scan dropped!");
+                                        state = State::Idle;
+                                        continue;
+                                    }
+                                    Delivery::Aborted => {
+                                        state = State::Idle;
+                                        continue;
+                                    }
+                                }
+                                
+                                // Batches go out in a sliding window, single points one by one
+                                let total = mock_data.len();
+                                let in_flight = if batch_size > 1 { MES_BATCH_WINDOW - 1 } else { 0 };
+                                let mut batch: Option<MesBatchPacket> = None;
+                                let mut mock_iter = first;
+                                let mut aborted = false;
+                                let mut lost = false;
+                                for (index, element) in mock_data.into_iter().enumerate().skip(first as usize) {
+                                    let (id, pack) = if batch_size > 1 {
+                                        let pending = batch.get_or_insert_with(|| MesBatchPacket::new(ids.next_id(), index as u32));
+                                        pending.push(element, 1000, MesStatus::Valid);
+                                        if (pending.count as usize) < batch_size && index + 1 < total {
+                                            std::thread::sleep(std::time::Duration::from_millis(dur));
+                                            continue;
+                                        }
+                                        let full = batch.take().unwrap();
+                                        println!("Sending batch of {:?} mock points", full.count);
+                                        (full.message_id(), full.encode())
+                                    } else {
+                                        println!("Sending mock point");
+                                        // Mock lidar reports a steady signal
+                                        let (line, point) = request.position(index as u32);
+                                        let mes = scanner_comms::packets::packet_mes::MesPacket::new(ids.next_id(), line, point, element, 1000, MesStatus::Valid);
+                                        (mes.message_id(), mes.encode())
+                                    };
                                     
-                                    let mut pack = Vec::with_capacity(12);
-        
-                                    let size = mes.serialize(pack.as_mut_ptr(), 12);
-                                    unsafe { pack.set_len(size); }
                                     port.write_all(&pack).unwrap();
+                                    transport.track(id, &pack, clock.elapsed().as_millis() as u32).unwrap();
                                     
-                                    let mut tbuf = [0u8;10];
-                                    port.read_exact(&mut tbuf).unwrap();
-                                    let mut tbuf = Vec::<u8>::from(tbuf);
-                                    
-                                    let obj = coder::decode_packet(&mut tbuf).unwrap();
-                                    match obj {
-                                        coder::PackType::Ok(_) => println!("Got Ack!"),
-                                        _ => panic!("Got something else than ok!"),
+                                    match await_ack(&mut port, &mut reader, &mut transport, &mut ids, &mut rx_filter, &clock, in_flight) {
+                                        Delivery::Acked => (),
+                                        Delivery::Lost => {
+                                            lost = true;
+                                            break;
+                                        }
+                                        Delivery::Aborted => {
+                                            aborted = true;
+                                            break;
+                                        }
                                     }
+                                    mock_iter = index as u32 + 1;
                                     std::thread::sleep(std::time::Duration::from_millis(dur));
                                 }
+                                
+                                // Batches still in the window have to be acknowledged before FIN
+                                if !aborted && !lost {
+                                    match await_ack(&mut port, &mut reader, &mut transport, &mut ids, &mut rx_filter, &clock, 0) {
+                                        Delivery::Acked => (),
+                                        Delivery::Lost => lost = true,
+                                        Delivery::Aborted => aborted = true,
+                                    }
+                                }
+                                
+                                // Dropped scan is not finished, the client resumes it or gives up
+                                if lost {
+                                    println!("Client does not respond, scan dropped!");
+                                    state = State::Idle;
+                                    continue;
+                                }
+                                
+                                // Aborted scan ends with the ok to ABORT, no FIN follows, FIN counts the points of the whole scan
+                                if aborted {
+                                    println!("Scan aborted after {:?} points", mock_iter);
+                                    state = State::Idle;
+                                    continue;
+                                }
+                                
+                                let fin = scanner_comms::packets::packet_fin::FinPacket::new(ids.next_id(), mock_iter);
+                                let pack = fin.encode();
+                                port.write_all(&pack).unwrap();
+                                transport.track(fin.message_id(), &pack, clock.elapsed().as_millis() as u32).unwrap();
+                                if !matches!(await_ack(&mut port, &mut reader, &mut transport, &mut ids, &mut rx_filter, &clock, 0), Delivery::Acked) {
+                                    println!("Client does not respond to fin!");
+                                }
+                                state = State::Idle;
                             }
                             State::Measure => {
                                 panic!("Critical error in comms, scan request sent while scanning!");
                             }
                         }
                     },  
+                    AnyPacket::Hello(pack) => {
+                        println!("Client speaks protocol version {:?}", pack.version);
+                        let resp = scanner_comms::packets::packet_ok::OkPacket::new(ids.next_id(), pack.message_id());
+                        port.write_all(&resp.encode()).unwrap();
+                        
+                        // 1.8 deg steppers, lines limited only by the packet
+                        let info = scanner_comms::packets::packet_info::InfoPacket::new(ids.next_id(), 0, 1, 0, 1_800_000, 1_800_000, u16::MAX, CAP_ABORT | CAP_MICROSTEPPING | CAP_RESUME);
+                        let pack = info.encode();
+                        port.write_all(&pack).unwrap();
+                        transport.track(info.message_id(), &pack, clock.elapsed().as_millis() as u32).unwrap();
+                        if !matches!(await_ack(&mut port, &mut reader, &mut transport, &mut ids, &mut rx_filter, &clock, 0), Delivery::Acked) {
+                            println!("Client does not respond to info!");
+                        }
+                    },
+                    AnyPacket::Abort(pack) => {
+                        println!("Nothing to abort");
+                        let resp = scanner_comms::packets::packet_ok::OkPacket::new(ids.next_id(), pack.message_id());
+                        port.write_all(&resp.encode()).unwrap();
+                    },
+                    AnyPacket::Mov(pack) => {
+                        println!("Moving {:?} steps", pack.steps);
+                        let resp = scanner_comms::packets::packet_ok::OkPacket::new(ids.next_id(), pack.message_id());
+                        port.write_all(&resp.encode()).unwrap();
+                    },
                     _ => {println!("Unknown pack!");},
                 }
             }
@@ -99,6 +211,167 @@ fn main() {
 }
 
 
-fn gen_data_points(lines: u8, points: u8) -> Vec<u32> {
-    (1..=lines as u32 *points as u32 + 1).collect()
+/// Scan programmed by PROG or RESUME.
+///
+/// number_of_points, number_of_lines - size of the scan grid
+/// point_step_size, line_step_size, microsteps - motor settings of the scan
+/// start - line and point of the first measurement to send
+struct ScanRequest {
+    number_of_points: u16,
+    number_of_lines: u16,
+    point_step_size: u16,
+    line_step_size: u16,
+    microsteps: u8,
+    start: (u16, u16),
+}
+
+impl ScanRequest {
+    fn from_packet(pack: &AnyPacket) -> Self {
+        match pack {
+            AnyPacket::Resume(pack) => ScanRequest {
+                number_of_points: pack.number_of_points,
+                number_of_lines: pack.number_of_lines,
+                point_step_size: pack.point_step_size,
+                line_step_size: pack.line_step_size,
+                microsteps: pack.microsteps,
+                start: (pack.start_line, pack.start_point),
+            },
+            AnyPacket::Prog(pack) => ScanRequest {
+                number_of_points: pack.number_of_points,
+                number_of_lines: pack.number_of_lines,
+                point_step_size: pack.point_step_size,
+                line_step_size: pack.line_step_size,
+                microsteps: pack.microsteps,
+                start: (0, 0),
+            },
+            _ => unreachable!("only PROG and RESUME program a scan"),
+        }
+    }
+
+    /// Measurement order of the start point, lines are scanned in a serpentine from the origin
+    fn first_index(&self) -> u32 {
+        let points = self.number_of_points.max(1) as u32;
+        let (line, point) = self.start;
+        let point = (point as u32).min(points - 1);
+        let offset = match line % 2 {
+            0 => point,
+            _ => points - 1 - point,
+        };
+        line as u32 * points + offset
+    }
+
+    /// Line and point of a measurement, the inverse of first_index
+    fn position(&self, index: u32) -> (u16, u16) {
+        let points = self.number_of_points.max(1) as u32;
+        let line = index / points;
+        let point = match line % 2 {
+            0 => index % points,
+            _ => points - 1 - index % points,
+        };
+        (line as u16, point as u16)
+    }
+}
+
+/// Outcome of waiting for an acknowledgement.
+///
+/// Acked - the client acknowledged the packet
+/// Lost - the retries ran out
+/// Aborted - the client aborted the scan meanwhile, the abort has been acknowledged
+enum Delivery {
+    Acked,
+    Lost,
+    Aborted,
+}
+
+/// Waits until the tracked packets are acknowledged, retransmits them on timeout.
+/// 
+/// in_flight - number of packets that may stay unacknowledged, 0 waits for all of them
+/// 
+/// @ret Delivery - how the wait ended
+fn await_ack<P: Read + Write>(port: &mut P, reader: &mut FrameReader, transport: &mut ReliableSender<MES_BATCH_WINDOW>, ids: &mut MessageIdAllocator, rx_filter: &mut DuplicateFilter<8>, clock: &std::time::Instant, in_flight: usize) -> Delivery {
+    while transport.pending() > in_flight {
+        match reader.next_packet(port) {
+            Ok(Ok(AnyPacket::Ok(ack))) => {
+                if transport.acknowledge(ack.acked_id) { println!("Got Ack!"); }
+                else { println!("Got Ack for unknown packet {:?}!", ack.acked_id); }
+            }
+            Ok(Ok(AnyPacket::Err(err))) => {
+                println!("Got error {:?} for packet {:?}", err.error as u8, err.packet_id);
+                transport.request_retransmit(err.packet_id);
+            }
+            Ok(Ok(AnyPacket::Abort(abort))) => {
+                // Acknowledged even when duplicate, the client retries until it gets the ok
+                let resp = scanner_comms::packets::packet_ok::OkPacket::new(ids.next_id(), abort.message_id());
+                port.write_all(&resp.encode()).unwrap();
+                if rx_filter.check(abort.message_id()) {
+                    transport.clear();
+                    return Delivery::Aborted;
+                }
+            }
+            Ok(Ok(_)) => println!("Got something else than ok!"),
+            Ok(Err(e)) => println!("Frame broken: {}", e),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => (),
+            Err(e) => panic!("Serial port error: {:?}", e),
+        }
+
+        while let Some(event) = transport.poll(clock.elapsed().as_millis() as u32) {
+            match event {
+                TransportEvent::Retransmit { id, frame } => {
+                    println!("Retransmitting {:?}", id);
+                    port.write_all(frame).unwrap();
+                }
+                TransportEvent::DeliveryFailed { id } => {
+                    println!("Packet {:?} lost!", id);
+                    return Delivery::Lost;
+                }
+            }
+        }
+    }
+    Delivery::Acked
+}
+
+/// Splits the serial stream into packets, keeps bytes received past the frame for the next call.
+struct FrameReader {
+    acc: FrameAccumulator<MAX_FRAME_SIZE>,
+    chunk: [u8; 64],
+    pos: usize,
+    len: usize,
+}
+
+impl FrameReader {
+    fn new() -> Self {
+        FrameReader { acc: FrameAccumulator::new(), chunk: [0; 64], pos: 0, len: 0 }
+    }
+
+    fn next_packet(&mut self, port: &mut impl Read) -> std::io::Result<Result<AnyPacket, DecodeError>> {
+        loop {
+            if self.pos == self.len {
+                self.len = port.read(&mut self.chunk)?;
+                self.pos = 0;
+            }
+            match self.acc.feed(&self.chunk[self.pos..self.len]) {
+                FeedResult::Consumed => self.pos = self.len,
+                FeedResult::Overflow { remaining } => {
+                    println!("Oversize frame dropped!");
+                    self.pos = self.len - remaining.len();
+                }
+                FeedResult::Frame { frame, remaining } => {
+                    let pack = AnyPacket::decode(frame);
+                    self.pos = self.len - remaining.len();
+                    return Ok(pack);
+                }
+            }
+        }
+    }
+}
+
+fn clock_seed() -> u16 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|t| t.subsec_nanos() as u16)
+        .unwrap_or(0)
+}
+
+fn gen_data_points(lines: u16, points: u16) -> Vec<u32> {
+    (1..=lines as u32 * points as u32).collect()
 }
\ No newline at end of file